@@ -0,0 +1,261 @@
+use crate::compression::create_output;
+use crate::config::Config;
+use crate::lang::LangKey;
+use crate::offset_unit::OffsetUnit;
+use crate::phrase_extractor::PhraseExtractor;
+use crate::rtok::RTok;
+use crate::textunit::Textunit;
+use crate::utf16::{substring, SubstringError};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::prelude::*;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AmphigramFormat {
+    #[default]
+    Tsv,
+    Jsonl,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Amphigram {
+    pub source: String,
+    pub target: String,
+    pub count: usize,
+    pub source_count: usize,
+    pub target_count: usize,
+}
+
+pub struct AmphigramCounter {
+    phrase_extractor: PhraseExtractor,
+    pair_counts: HashMap<(String, String), usize>,
+    source_counts: HashMap<String, usize>,
+    target_counts: HashMap<String, usize>,
+}
+
+fn surface(text: &str, rtoks: &[RTok], s: usize, e: usize) -> Result<String, SubstringError> {
+    substring(text, rtoks[s].s, rtoks[e - 1].e)
+}
+
+fn escape_tsv(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+impl AmphigramCounter {
+    pub fn new(phrase_extractor: PhraseExtractor) -> AmphigramCounter {
+        AmphigramCounter {
+            phrase_extractor,
+            pair_counts: HashMap::new(),
+            source_counts: HashMap::new(),
+            target_counts: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, textunit: &Textunit) -> Result<(), Box<dyn Error>> {
+        let source_rtoks = textunit.rtoks_in(LangKey::SOURCE, OffsetUnit::Utf16)?;
+        let target_rtoks = textunit.rtoks_in(LangKey::TARGET, OffsetUnit::Utf16)?;
+        for phrase_pair in self.phrase_extractor.extract(textunit) {
+            let source = surface(
+                &textunit.bi_text.source,
+                &source_rtoks,
+                phrase_pair.source.s,
+                phrase_pair.source.e,
+            )?;
+            let target = surface(
+                &textunit.bi_text.target,
+                &target_rtoks,
+                phrase_pair.target.s,
+                phrase_pair.target.e,
+            )?;
+            *self.source_counts.entry(source.clone()).or_insert(0) += 1;
+            *self.target_counts.entry(target.clone()).or_insert(0) += 1;
+            *self.pair_counts.entry((source, target)).or_insert(0) += 1;
+        }
+        Ok(())
+    }
+
+    /// Returns amphigrams sorted by descending count, then by text.
+    /// `source_count` and `target_count` are the number of extracted pairs
+    /// sharing the same source or target side.
+    pub fn amphigrams(&self) -> Vec<Amphigram> {
+        let mut amphigrams: Vec<_> = self
+            .pair_counts
+            .iter()
+            .map(|((source, target), count)| Amphigram {
+                source: source.clone(),
+                target: target.clone(),
+                count: *count,
+                source_count: self.source_counts[source],
+                target_count: self.target_counts[target],
+            })
+            .collect();
+        amphigrams.sort_by(|a, b| {
+            b.count
+                .cmp(&a.count)
+                .then_with(|| a.source.cmp(&b.source))
+                .then_with(|| a.target.cmp(&b.target))
+        });
+        amphigrams
+    }
+}
+
+/// TSV lines are `source<TAB>target<TAB>count<TAB>source_count<TAB>target_count`
+/// with backslash, tab and newline escaped as `\\`, `\t` and `\n`.
+/// JSONL lines are serialized `Amphigram` objects.
+pub fn write_amphigrams<W: Write>(
+    w: &mut W,
+    amphigrams: &[Amphigram],
+    format: AmphigramFormat,
+) -> Result<(), Box<dyn Error>> {
+    for amphigram in amphigrams {
+        match format {
+            AmphigramFormat::Tsv => writeln!(
+                w,
+                "{}\t{}\t{}\t{}\t{}",
+                escape_tsv(&amphigram.source),
+                escape_tsv(&amphigram.target),
+                amphigram.count,
+                amphigram.source_count,
+                amphigram.target_count
+            )?,
+            AmphigramFormat::Jsonl => {
+                serde_json::to_writer(&mut *w, amphigram)?;
+                writeln!(w)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn output_amphigrams<'a, I>(
+    config: &Config,
+    textunits: I,
+    phrase_extractor: PhraseExtractor,
+) -> Result<(), Box<dyn Error>>
+where
+    I: IntoIterator<Item = &'a Textunit>,
+{
+    let mut counter = AmphigramCounter::new(phrase_extractor);
+    for textunit in textunits {
+        counter.add(textunit)?;
+    }
+    let mut w = create_output(&config.output_amphigram_path)?;
+    write_amphigrams(
+        &mut w,
+        &counter.amphigrams(),
+        config.output_amphigram_format,
+    )?;
+    w.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bi_rtoks::BiRToks;
+    use crate::bi_text::BiText;
+    use crate::link::Link;
+    use crate::reader::Reader;
+    use crate::rtoks_builder::RToksBuilder;
+
+    fn black_cat() -> Textunit {
+        let rtoks_builder = RToksBuilder::new();
+        let bi_text = BiText {
+            source: String::from("black cat"),
+            target: String::from("แมวดำ"),
+        };
+        let bi_rtoks = BiRToks {
+            source: rtoks_builder
+                .align_text_toks(&bi_text.source, &Reader::parse_toks("black cat"))
+                .unwrap(),
+            target: rtoks_builder
+                .align_text_toks(&bi_text.target, &Reader::parse_toks("แมว ดำ"))
+                .unwrap(),
+            source_factors: vec![],
+            target_factors: vec![],
+            offset_unit: OffsetUnit::Utf16,
+        };
+        Textunit {
+            bi_text,
+            bi_rtoks,
+            links: vec![
+                Link {
+                    source: 0,
+                    target: 1,
+                },
+                Link {
+                    source: 1,
+                    target: 0,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn count_amphigrams() {
+        let mut counter = AmphigramCounter::new(PhraseExtractor::new(2));
+        counter.add(&black_cat()).unwrap();
+        counter.add(&black_cat()).unwrap();
+        let amphigrams = counter.amphigrams();
+        assert_eq!(amphigrams.len(), 3);
+        assert_eq!(
+            amphigrams[0],
+            Amphigram {
+                source: String::from("black"),
+                target: String::from("ดำ"),
+                count: 2,
+                source_count: 2,
+                target_count: 2,
+            }
+        );
+        assert_eq!(amphigrams[1].source, "black cat");
+        assert_eq!(amphigrams[1].target, "แมวดำ");
+    }
+
+    #[test]
+    fn count_amphigrams_in_other_offset_unit() {
+        let source_rtoks_builder = RToksBuilder::new().offset_unit(OffsetUnit::Grapheme);
+        let target_rtoks_builder = RToksBuilder::new().offset_unit(OffsetUnit::Utf8);
+        let bi_text = BiText {
+            source: String::from("black cat"),
+            target: String::from("น้ำดำ"),
+        };
+        let textunit = Textunit::align_with_builders(
+            &source_rtoks_builder,
+            &target_rtoks_builder,
+            1,
+            bi_text,
+            &Reader::parse_toks("black cat"),
+            &Reader::parse_toks("น้ำ ดำ"),
+            Reader::parse_links("0-1 1-0").unwrap(),
+        )
+        .unwrap();
+        assert_eq!(textunit.bi_rtoks.offset_unit, OffsetUnit::Grapheme);
+        let target = &textunit.bi_rtoks.target[1];
+        assert_eq!((target.s, target.e), (1, 2));
+        let mut counter = AmphigramCounter::new(PhraseExtractor::new(2));
+        counter.add(&textunit).unwrap();
+        let amphigrams = counter.amphigrams();
+        assert_eq!(amphigrams[0].source, "black");
+        assert_eq!(amphigrams[0].target, "ดำ");
+        assert_eq!(amphigrams[1].target, "น้ำดำ");
+    }
+
+    #[test]
+    fn write_amphigrams_as_tsv() {
+        let amphigrams = vec![Amphigram {
+            source: String::from("a\tb"),
+            target: String::from("c"),
+            count: 3,
+            source_count: 4,
+            target_count: 5,
+        }];
+        let mut out = vec![];
+        write_amphigrams(&mut out, &amphigrams, AmphigramFormat::Tsv).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a\\tb\tc\t3\t4\t5\n");
+    }
+}
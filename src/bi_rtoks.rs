@@ -1,10 +1,21 @@
 use crate::lang::LangKey;
+use crate::offset_unit::OffsetUnit;
 use crate::rtok::RTok;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct BiRToks {
     pub source: Vec<RTok>,
     pub target: Vec<RTok>,
+    /// Non-surface factors of each source token, e.g. `["NN", "cat"]` for
+    /// `cat|NN|cat`; empty if the tokens are not factored.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub source_factors: Vec<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub target_factors: Vec<Vec<String>>,
+    /// The unit of `s` and `e` of both sides.
+    #[serde(default)]
+    pub offset_unit: OffsetUnit,
 }
 
 impl BiRToks {
@@ -14,4 +25,11 @@ impl BiRToks {
             LangKey::TARGET => self.target.clone(),
         }
     }
+
+    pub fn factors(&self, lang_key: LangKey) -> &[Vec<String>] {
+        match lang_key {
+            LangKey::SOURCE => &self.source_factors,
+            LangKey::TARGET => &self.target_factors,
+        }
+    }
 }
@@ -0,0 +1,388 @@
+use crate::bi_text::BiText;
+use crate::link::Link;
+use crate::reader::{LineIter, ParsingError, Reader};
+use crate::rtoks_builder::RToksBuilder;
+use crate::textunit::{Textunit, TextunitLoadingError};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::error::Error;
+
+/// 0-based TSV column of each part of a record.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BitextColumns {
+    pub source: usize,
+    pub target: usize,
+    pub source_toks: usize,
+    pub target_toks: usize,
+    pub links: usize,
+}
+
+impl Default for BitextColumns {
+    fn default() -> BitextColumns {
+        BitextColumns {
+            source: 0,
+            target: 1,
+            source_toks: 2,
+            target_toks: 3,
+            links: 4,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BitextFields {
+    pub source: String,
+    pub target: String,
+    pub source_toks: String,
+    pub target_toks: String,
+    pub links: String,
+}
+
+impl Default for BitextFields {
+    fn default() -> BitextFields {
+        BitextFields {
+            source: String::from("source"),
+            target: String::from("target"),
+            source_toks: String::from("source_toks"),
+            target_toks: String::from("target_toks"),
+            links: String::from("links"),
+        }
+    }
+}
+
+/// TSV tokens and links are space separated, as in the multi-file format.
+/// JSONL tokens may be a string or an array of strings, and links a
+/// Pharaoh string, an array of `{"source":_,"target":_}` objects or an
+/// array of `[source, target]` pairs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum BitextFormat {
+    Tsv(BitextColumns),
+    Jsonl(BitextFields),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BitextRecord {
+    pub bi_text: BiText,
+    pub source_toks: Vec<String>,
+    pub target_toks: Vec<String>,
+    pub source_factors: Vec<Vec<String>>,
+    pub target_factors: Vec<Vec<String>>,
+    pub links: Vec<Link>,
+}
+
+impl BitextRecord {
+    /// Tokens are split into the surface form and the other factors if
+    /// `separator` is given.
+    pub fn parse(
+        line: &str,
+        format: &BitextFormat,
+        separator: Option<&str>,
+    ) -> Result<BitextRecord, Box<dyn Error>> {
+        match format {
+            BitextFormat::Tsv(columns) => Ok(Self::parse_tsv(line, columns, separator)?),
+            BitextFormat::Jsonl(fields) => Self::parse_jsonl(line, fields, separator),
+        }
+    }
+
+    pub fn parse_tsv(
+        line: &str,
+        columns: &BitextColumns,
+        separator: Option<&str>,
+    ) -> Result<BitextRecord, ParsingError> {
+        let cols: Vec<&str> = line.split('\t').collect();
+        let col = |i: usize| cols.get(i).copied().ok_or(ParsingError::MissingColumn(i));
+        let (source_toks, source_factors) =
+            Reader::parse_toks_with_factors(col(columns.source_toks)?, separator);
+        let (target_toks, target_factors) =
+            Reader::parse_toks_with_factors(col(columns.target_toks)?, separator);
+        Ok(BitextRecord {
+            bi_text: BiText {
+                source: col(columns.source)?.to_string(),
+                target: col(columns.target)?.to_string(),
+            },
+            source_toks,
+            target_toks,
+            source_factors,
+            target_factors,
+            links: Reader::parse_links(col(columns.links)?)?,
+        })
+    }
+
+    pub fn parse_jsonl(
+        line: &str,
+        fields: &BitextFields,
+        separator: Option<&str>,
+    ) -> Result<BitextRecord, Box<dyn Error>> {
+        let record: Value = serde_json::from_str(line)?;
+        let field = |name: &str| {
+            record
+                .get(name)
+                .ok_or_else(|| ParsingError::MissingField(name.to_string()))
+        };
+        let text = |name: &str| -> Result<String, ParsingError> {
+            field(name)?
+                .as_str()
+                .map(|s| s.to_string())
+                .ok_or_else(|| ParsingError::InvalidField(name.to_string()))
+        };
+        let (source_toks, source_factors) =
+            json_toks(field(&fields.source_toks)?, &fields.source_toks, separator)?;
+        let (target_toks, target_factors) =
+            json_toks(field(&fields.target_toks)?, &fields.target_toks, separator)?;
+        Ok(BitextRecord {
+            bi_text: BiText {
+                source: text(&fields.source)?,
+                target: text(&fields.target)?,
+            },
+            source_toks,
+            target_toks,
+            source_factors,
+            target_factors,
+            links: json_links(field(&fields.links)?, &fields.links)?,
+        })
+    }
+
+    pub fn into_textunit(
+        self,
+        source_rtoks_builder: &RToksBuilder,
+        target_rtoks_builder: &RToksBuilder,
+        line_no: usize,
+    ) -> Result<Textunit, TextunitLoadingError> {
+        let textunit = Textunit::align_with_builders(
+            source_rtoks_builder,
+            target_rtoks_builder,
+            line_no,
+            self.bi_text,
+            &self.source_toks,
+            &self.target_toks,
+            self.links,
+        )?;
+        Ok(textunit.with_factors(self.source_factors, self.target_factors))
+    }
+}
+
+fn json_toks(
+    value: &Value,
+    name: &str,
+    separator: Option<&str>,
+) -> Result<(Vec<String>, Vec<Vec<String>>), ParsingError> {
+    let invalid = || ParsingError::InvalidField(name.to_string());
+    match value {
+        Value::String(toks) => Ok(Reader::parse_toks_with_factors(toks, separator)),
+        Value::Array(toks) => {
+            let toks = toks
+                .iter()
+                .map(|tok| tok.as_str().ok_or_else(invalid))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(match separator {
+                Some(separator) => toks
+                    .into_iter()
+                    .map(|tok| Reader::parse_factored_tok(tok, separator))
+                    .unzip(),
+                None => (
+                    toks.into_iter().map(|tok| tok.to_string()).collect(),
+                    vec![],
+                ),
+            })
+        }
+        _ => Err(invalid()),
+    }
+}
+
+fn json_links(value: &Value, name: &str) -> Result<Vec<Link>, ParsingError> {
+    let invalid = || ParsingError::InvalidField(name.to_string());
+    let index = |value: Option<&Value>| {
+        value
+            .and_then(|v| v.as_u64())
+            .map(|v| v as usize)
+            .ok_or_else(invalid)
+    };
+    match value {
+        Value::String(links) => Reader::parse_links(links),
+        Value::Array(links) => links
+            .iter()
+            .map(|link| match link {
+                Value::Array(pair) if pair.len() == 2 => Ok(Link {
+                    source: index(pair.first())?,
+                    target: index(pair.get(1))?,
+                }),
+                Value::Object(_) => Ok(Link {
+                    source: index(link.get("source"))?,
+                    target: index(link.get("target"))?,
+                }),
+                _ => Err(invalid()),
+            })
+            .collect(),
+        _ => Err(invalid()),
+    }
+}
+
+pub struct BitextIter<'a> {
+    source_rtoks_builder: &'a RToksBuilder,
+    target_rtoks_builder: &'a RToksBuilder,
+    format: BitextFormat,
+    lines: LineIter,
+    line_no: usize,
+    remaining: usize,
+    factor_separator: Option<String>,
+}
+
+impl<'a> BitextIter<'a> {
+    /// Honors `textunit_offset` and `textunit_limit` of the reader's config.
+    pub fn new(
+        reader: &Reader,
+        path: &str,
+        format: BitextFormat,
+        source_rtoks_builder: &'a RToksBuilder,
+        target_rtoks_builder: &'a RToksBuilder,
+    ) -> Result<BitextIter<'a>, TextunitLoadingError> {
+        let lines = reader
+            .open_lines(path)
+            .map_err(|err| TextunitLoadingError::CannotReadFile(path.to_string(), err))?;
+        Ok(BitextIter {
+            source_rtoks_builder,
+            target_rtoks_builder,
+            format,
+            lines,
+            line_no: reader.config.textunit_offset,
+            remaining: reader.config.textunit_limit,
+            factor_separator: reader.config.factor_separator.clone(),
+        })
+    }
+}
+
+impl<'a> Iterator for BitextIter<'a> {
+    type Item = Result<Textunit, TextunitLoadingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let line = match self.lines.next()? {
+            Ok(line) => line,
+            Err(err) => {
+                return Some(Err(TextunitLoadingError::CannotParseRecord(
+                    self.line_no + 1,
+                    Box::new(err),
+                )))
+            }
+        };
+        self.line_no += 1;
+        Some(
+            BitextRecord::parse(&line, &self.format, self.factor_separator.as_deref())
+                .map_err(|err| TextunitLoadingError::CannotParseRecord(self.line_no, err))
+                .and_then(|record| {
+                    record.into_textunit(
+                        self.source_rtoks_builder,
+                        self.target_rtoks_builder,
+                        self.line_no,
+                    )
+                }),
+        )
+    }
+}
+
+pub fn load_bitext(
+    reader: &Reader,
+    path: &str,
+    format: BitextFormat,
+    source_rtoks_builder: &RToksBuilder,
+    target_rtoks_builder: &RToksBuilder,
+) -> Result<Vec<Textunit>, Box<dyn Error>> {
+    let mut textunits = vec![];
+    let iter = BitextIter::new(
+        reader,
+        path,
+        format,
+        source_rtoks_builder,
+        target_rtoks_builder,
+    )?;
+    for textunit in iter {
+        textunits.push(textunit?);
+    }
+    Ok(textunits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, Langs};
+
+    fn multi_reader() -> Reader {
+        let root = env!("CARGO_MANIFEST_DIR");
+        let langs = Langs {
+            source: String::from("en"),
+            target: String::from("th"),
+        };
+        let conf = Config {
+            corpus_dir: format!("{}/data", root),
+            tok_prefix: String::from("multi-toks"),
+            langs,
+            alignment_file_path: format!("{}/data/multi-links", root),
+            orig_prefix: String::from("multi-lines"),
+            ..Config::default()
+        };
+        Reader { config: conf }
+    }
+
+    #[test]
+    fn load_tsv_like_multi_file() {
+        let root = env!("CARGO_MANIFEST_DIR");
+        let reader = multi_reader();
+        let rtoks_builder = RToksBuilder::new();
+        let expected = Textunit::load(&reader, &rtoks_builder, &rtoks_builder).unwrap();
+        let textunits = load_bitext(
+            &reader,
+            &format!("{}/data/multi.tsv", root),
+            BitextFormat::Tsv(BitextColumns::default()),
+            &rtoks_builder,
+            &rtoks_builder,
+        )
+        .unwrap();
+        assert_eq!(textunits, expected);
+    }
+
+    #[test]
+    fn load_jsonl_with_custom_fields() {
+        let root = env!("CARGO_MANIFEST_DIR");
+        let reader = multi_reader();
+        let rtoks_builder = RToksBuilder::new();
+        let expected = Textunit::load(&reader, &rtoks_builder, &rtoks_builder).unwrap();
+        let fields = BitextFields {
+            source: String::from("en"),
+            target: String::from("th"),
+            source_toks: String::from("en_toks"),
+            target_toks: String::from("th_toks"),
+            links: String::from("alignment"),
+        };
+        let textunits = load_bitext(
+            &reader,
+            &format!("{}/data/multi.jsonl", root),
+            BitextFormat::Jsonl(fields),
+            &rtoks_builder,
+            &rtoks_builder,
+        )
+        .unwrap();
+        assert_eq!(textunits, expected);
+    }
+
+    #[test]
+    fn parse_factored_toks() {
+        let rtoks_builder = RToksBuilder::new();
+        let line = "black cat\tแมวดำ\tblack|JJ cat|NN\tแมว|NOUN ดำ|ADJ\t1-0 0-1";
+        let tsv = BitextRecord::parse_tsv(line, &BitextColumns::default(), Some("|"))
+            .unwrap()
+            .into_textunit(&rtoks_builder, &rtoks_builder, 1)
+            .unwrap();
+        assert_eq!(tsv.bi_rtoks.source[1].text, "cat");
+        assert_eq!(tsv.bi_rtoks.source_factors, vec![vec!["JJ"], vec!["NN"]]);
+        assert_eq!(tsv.bi_rtoks.target_factors, vec![vec!["NOUN"], vec!["ADJ"]]);
+        let json = r#"{"source": "black cat", "target": "แมวดำ", "source_toks": ["black|JJ", "cat|NN"], "target_toks": "แมว|NOUN ดำ|ADJ", "links": "1-0 0-1"}"#;
+        let jsonl = BitextRecord::parse_jsonl(json, &BitextFields::default(), Some("|"))
+            .unwrap()
+            .into_textunit(&rtoks_builder, &rtoks_builder, 1)
+            .unwrap();
+        assert_eq!(jsonl, tsv);
+    }
+}
@@ -0,0 +1,151 @@
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const XZ_MAGIC: &[u8] = &[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    Plain,
+    Gzip,
+    Zstd,
+    Xz,
+}
+
+impl Compression {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Compression {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") | Some("zstd") => Compression::Zstd,
+            Some("xz") => Compression::Xz,
+            _ => Compression::Plain,
+        }
+    }
+
+    pub fn from_magic(bytes: &[u8]) -> Option<Compression> {
+        if bytes.starts_with(GZIP_MAGIC) {
+            Some(Compression::Gzip)
+        } else if bytes.starts_with(ZSTD_MAGIC) {
+            Some(Compression::Zstd)
+        } else if bytes.starts_with(XZ_MAGIC) {
+            Some(Compression::Xz)
+        } else {
+            None
+        }
+    }
+}
+
+/// Opens `path` for reading, decompressing it on the fly when its magic
+/// bytes, or failing that its extension, say it is compressed.
+pub fn open_input<P: AsRef<Path>>(path: P) -> io::Result<Box<dyn BufRead>> {
+    let mut reader = BufReader::new(File::open(&path)?);
+    let compression = Compression::from_magic(reader.fill_buf()?)
+        .unwrap_or_else(|| Compression::from_path(&path));
+    Ok(match compression {
+        Compression::Plain => Box::new(reader),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Compression::Zstd => Box::new(BufReader::new(zstd::stream::read::Decoder::with_buffer(
+            reader,
+        )?)),
+        Compression::Xz => Box::new(BufReader::new(xz2::read::XzDecoder::new_multi_decoder(
+            reader,
+        ))),
+    })
+}
+
+/// A compressing writer from `create_output`. `finish` must be called to
+/// write the end of the compressed stream and to see any error doing so.
+pub enum OutputWriter {
+    Plain(BufWriter<File>),
+    Gzip(GzEncoder<BufWriter<File>>),
+    Zstd(zstd::stream::write::Encoder<'static, BufWriter<File>>),
+    Xz(xz2::write::XzEncoder<BufWriter<File>>),
+}
+
+impl OutputWriter {
+    pub fn finish(self) -> io::Result<()> {
+        let mut writer = match self {
+            OutputWriter::Plain(writer) => writer,
+            OutputWriter::Gzip(encoder) => encoder.finish()?,
+            OutputWriter::Zstd(encoder) => encoder.finish()?,
+            OutputWriter::Xz(encoder) => encoder.finish()?,
+        };
+        writer.flush()
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputWriter::Plain(writer) => writer.write(buf),
+            OutputWriter::Gzip(encoder) => encoder.write(buf),
+            OutputWriter::Zstd(encoder) => encoder.write(buf),
+            OutputWriter::Xz(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputWriter::Plain(writer) => writer.flush(),
+            OutputWriter::Gzip(encoder) => encoder.flush(),
+            OutputWriter::Zstd(encoder) => encoder.flush(),
+            OutputWriter::Xz(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Creates `path` for writing, compressing by its extension.
+pub fn create_output<P: AsRef<Path>>(path: P) -> io::Result<OutputWriter> {
+    let writer = BufWriter::new(File::create(&path)?);
+    Ok(match Compression::from_path(&path) {
+        Compression::Plain => OutputWriter::Plain(writer),
+        Compression::Gzip => {
+            OutputWriter::Gzip(GzEncoder::new(writer, flate2::Compression::default()))
+        }
+        Compression::Zstd => OutputWriter::Zstd(zstd::stream::write::Encoder::new(writer, 0)?),
+        Compression::Xz => OutputWriter::Xz(xz2::write::XzEncoder::new(writer, 6)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn round_trip(name: &str) {
+        let path = env::temp_dir().join(format!("parallel_corpus_tool_{}", name));
+        let mut w = create_output(&path).unwrap();
+        w.write_all("ABC\nแมวดำ\n".as_bytes()).unwrap();
+        w.finish().unwrap();
+        let lines: Vec<_> = open_input(&path)
+            .unwrap()
+            .lines()
+            .map(|line| line.unwrap())
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(lines, vec!["ABC", "แมวดำ"]);
+    }
+
+    #[test]
+    fn round_trip_compressed_files() {
+        round_trip("plain.txt");
+        round_trip("gzip.gz");
+        round_trip("zstd.zst");
+        round_trip("xz.xz");
+    }
+
+    #[test]
+    fn detect_by_magic() {
+        assert_eq!(
+            Compression::from_magic(&[0x1f, 0x8b, 0x08]),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(Compression::from_magic(b"1-2 3-4"), None);
+    }
+}
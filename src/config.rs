@@ -1,5 +1,9 @@
+use crate::amphigram::AmphigramFormat;
+use crate::moses::MosesEscaping;
+use crate::rtoks_builder::RuleStrMod;
 use config;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Langs {
@@ -15,8 +19,39 @@ pub struct Config {
     pub alignment_file_path: String,
     pub orig_prefix: String,
     pub output_amphigram_path: String,
+    #[serde(default)]
+    pub output_amphigram_format: AmphigramFormat,
     pub textunit_limit: usize,
     pub textunit_offset: usize,
+    #[serde(default)]
+    pub factor_separator: Option<String>,
+    #[serde(default)]
+    pub normalizers: Vec<RuleStrMod>,
+    /// Moses escaping by language code; languages not listed use the default.
+    #[serde(default)]
+    pub moses_escaping: HashMap<String, MosesEscaping>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            corpus_dir: String::new(),
+            tok_prefix: String::new(),
+            langs: Langs {
+                source: String::new(),
+                target: String::new(),
+            },
+            alignment_file_path: String::new(),
+            orig_prefix: String::new(),
+            output_amphigram_path: String::new(),
+            output_amphigram_format: AmphigramFormat::default(),
+            textunit_limit: usize::MAX,
+            textunit_offset: 0,
+            factor_separator: None,
+            normalizers: vec![],
+            moses_escaping: HashMap::new(),
+        }
+    }
 }
 
 impl Config {
@@ -27,4 +62,8 @@ impl Config {
         let config: Config = settings.try_into::<Config>().unwrap();
         config
     }
+
+    pub fn moses_escaping(&self, lang: &str) -> MosesEscaping {
+        self.moses_escaping.get(lang).cloned().unwrap_or_default()
+    }
 }
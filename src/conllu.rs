@@ -0,0 +1,350 @@
+use crate::lang::LangKey;
+use crate::link::Link;
+use crate::rtok::RTok;
+use crate::rtoks_builder::{CharAlignError, RToksBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::prelude::*;
+
+const ALIGN_MISC: &str = "Align";
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum ConlluError {
+        Columns(line: String) {
+            display("Expected 10 columns: {}", line)
+        }
+        Head(head: usize) {
+            display("HEAD {} does not refer to a token", head)
+        }
+        Deps(deps: String) {
+            display("Invalid DEPS {}", deps)
+        }
+        CountMismatch(sentences: usize, links: usize) {
+            display("Sentence and links counts differ: {} {}", sentences, links)
+        }
+    }
+}
+
+/// How to read multiword tokens such as `1-2 del` followed by `1 de`, `2 el`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MultiwordMode {
+    /// Skip the range line and keep the syntactic words.
+    Skip,
+    /// Keep the surface form of the range instead of its words.
+    Surface,
+}
+
+/// The columns of one token besides FORM; `_` in the file becomes `None`.
+/// `id` is the original ID, e.g. `4` or `2-3` for a multiword token, and
+/// `head` the raw HEAD column, i.e. a 1-based word ID or 0 for the root.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct TokAnnotation {
+    pub id: String,
+    pub lemma: Option<String>,
+    pub upos: Option<String>,
+    pub xpos: Option<String>,
+    pub feats: Option<String>,
+    pub head: Option<usize>,
+    pub deprel: Option<String>,
+    pub deps: Option<String>,
+    pub misc: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ConlluSentence {
+    pub comments: Vec<String>,
+    pub toks: Vec<String>,
+    pub annotations: Vec<TokAnnotation>,
+}
+
+fn field(value: &str) -> Option<String> {
+    if value == "_" {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+impl ConlluSentence {
+    /// The `# text = ...` comment, if any.
+    pub fn text(&self) -> Option<&str> {
+        self.comments
+            .iter()
+            .filter_map(|comment| comment.strip_prefix("text ="))
+            .map(|text| text.trim())
+            .next()
+    }
+
+    /// Aligns the tokens to `text` and pairs each `RTok` with its annotation.
+    pub fn align(
+        &self,
+        text: &str,
+        rtoks_builder: &RToksBuilder,
+    ) -> Result<Vec<(RTok, TokAnnotation)>, CharAlignError> {
+        let rtoks = rtoks_builder.align_text_toks(text, &self.toks)?;
+        Ok(rtoks
+            .into_iter()
+            .zip(self.annotations.iter().cloned())
+            .collect())
+    }
+}
+
+struct Range {
+    s: usize,
+    e: usize,
+}
+
+fn parse_annotation(cols: &[&str]) -> Result<TokAnnotation, Box<dyn Error>> {
+    Ok(TokAnnotation {
+        id: cols[0].to_string(),
+        lemma: field(cols[2]),
+        upos: field(cols[3]),
+        xpos: field(cols[4]),
+        feats: field(cols[5]),
+        head: match cols[6] {
+            "_" => None,
+            head => Some(head.parse()?),
+        },
+        deprel: field(cols[7]),
+        deps: field(cols[8]),
+        misc: field(cols[9]),
+    })
+}
+
+pub fn read_conllu<R: BufRead>(
+    r: R,
+    mode: MultiwordMode,
+) -> Result<Vec<ConlluSentence>, Box<dyn Error>> {
+    let mut sentences = vec![];
+    let mut sentence = ConlluSentence::default();
+    let mut range: Option<Range> = None;
+    for line in r.lines() {
+        let line = line?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            if !sentence.toks.is_empty() || !sentence.comments.is_empty() {
+                sentences.push(std::mem::take(&mut sentence));
+            }
+            range = None;
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            sentence.comments.push(comment.trim().to_string());
+            continue;
+        }
+        let cols: Vec<&str> = line.split('\t').collect();
+        if cols.len() != 10 {
+            return Err(From::from(ConlluError::Columns(line.to_string())));
+        }
+        let id = cols[0];
+        if id.contains('.') {
+            // empty node
+            continue;
+        }
+        if let Some((s, e)) = id.split_once('-') {
+            let s: usize = s.parse()?;
+            let e: usize = e.parse()?;
+            range = Some(Range { s, e });
+            if mode == MultiwordMode::Surface {
+                sentence.toks.push(cols[1].to_string());
+                sentence.annotations.push(parse_annotation(&cols)?);
+            }
+            continue;
+        }
+        let id: usize = id.parse()?;
+        if mode == MultiwordMode::Surface {
+            if let Some(r) = &range {
+                if id >= r.s && id <= r.e {
+                    continue;
+                }
+            }
+        }
+        sentence.toks.push(cols[1].to_string());
+        sentence.annotations.push(parse_annotation(&cols)?);
+    }
+    if !sentence.toks.is_empty() || !sentence.comments.is_empty() {
+        sentences.push(sentence);
+    }
+    Ok(sentences)
+}
+
+/// Maps original word IDs to the new 1-based token IDs; all words of a
+/// multiword token map to the token.
+fn id_map(annotations: &[TokAnnotation]) -> Result<HashMap<usize, usize>, Box<dyn Error>> {
+    let mut ids = HashMap::new();
+    for (i, annotation) in annotations.iter().enumerate() {
+        match annotation.id.split_once('-') {
+            Some((s, e)) => {
+                for id in s.parse::<usize>()?..=e.parse::<usize>()? {
+                    ids.insert(id, i + 1);
+                }
+            }
+            None if annotation.id.is_empty() => {}
+            None => {
+                ids.insert(annotation.id.parse()?, i + 1);
+            }
+        }
+    }
+    Ok(ids)
+}
+
+fn remap_head(head: usize, ids: &HashMap<usize, usize>) -> Result<usize, Box<dyn Error>> {
+    if head == 0 {
+        return Ok(0);
+    }
+    ids.get(&head)
+        .cloned()
+        .ok_or_else(|| From::from(ConlluError::Head(head)))
+}
+
+/// Remaps the heads of enhanced dependencies, dropping those on empty nodes.
+fn remap_deps(deps: &str, ids: &HashMap<usize, usize>) -> Result<Option<String>, Box<dyn Error>> {
+    let mut remapped = vec![];
+    for dep in deps.split('|') {
+        let (head, rel) = dep
+            .split_once(':')
+            .ok_or_else(|| ConlluError::Deps(deps.to_string()))?;
+        if head.contains('.') {
+            continue;
+        }
+        remapped.push(format!("{}:{}", remap_head(head.parse()?, ids)?, rel));
+    }
+    Ok(if remapped.is_empty() {
+        None
+    } else {
+        Some(remapped.join("|"))
+    })
+}
+
+fn misc_with_align(misc: Option<&str>, aligned: &[usize]) -> String {
+    let mut entries: Vec<String> = misc
+        .map(|misc| {
+            misc.split('|')
+                .filter(|entry| !entry.starts_with(&format!("{}=", ALIGN_MISC)))
+                .map(|entry| entry.to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    if !aligned.is_empty() {
+        let aligned: Vec<_> = aligned.iter().map(|j| j.to_string()).collect();
+        entries.push(format!("{}={}", ALIGN_MISC, aligned.join(",")));
+    }
+    if entries.is_empty() {
+        String::from("_")
+    } else {
+        entries.join("|")
+    }
+}
+
+/// Writes `sentences` for one side of the corpus with tokens renumbered
+/// from 1 and HEAD and DEPS remapped accordingly. `Align=` followed by the
+/// comma separated 0-based indices of the tokens linked on the other side
+/// is added to the MISC column of each token.
+pub fn write_conllu<W: Write>(
+    w: &mut W,
+    sentences: &[ConlluSentence],
+    links_list: &[Vec<Link>],
+    lang_key: LangKey,
+) -> Result<(), Box<dyn Error>> {
+    if sentences.len() != links_list.len() {
+        return Err(From::from(ConlluError::CountMismatch(
+            sentences.len(),
+            links_list.len(),
+        )));
+    }
+    for (sentence, links) in sentences.iter().zip(links_list.iter()) {
+        for comment in &sentence.comments {
+            writeln!(w, "# {}", comment)?;
+        }
+        let ids = id_map(&sentence.annotations)?;
+        for (i, tok) in sentence.toks.iter().enumerate() {
+            let annotation = sentence.annotations.get(i).cloned().unwrap_or_default();
+            let mut aligned: Vec<usize> = links
+                .iter()
+                .filter_map(|link| match lang_key {
+                    LangKey::SOURCE if link.source == i => Some(link.target),
+                    LangKey::TARGET if link.target == i => Some(link.source),
+                    _ => None,
+                })
+                .collect();
+            aligned.sort_unstable();
+            let head = match annotation.head {
+                Some(head) => remap_head(head, &ids)?.to_string(),
+                None => String::from("_"),
+            };
+            let deps = match &annotation.deps {
+                Some(deps) => remap_deps(deps, &ids)?,
+                None => None,
+            };
+            let col = |value: &Option<String>| value.clone().unwrap_or_else(|| String::from("_"));
+            writeln!(
+                w,
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                i + 1,
+                tok,
+                col(&annotation.lemma),
+                col(&annotation.upos),
+                col(&annotation.xpos),
+                col(&annotation.feats),
+                head,
+                col(&annotation.deprel),
+                col(&deps),
+                misc_with_align(annotation.misc.as_deref(), &aligned)
+            )?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::Reader;
+
+    const CONLLU: &str = "# sent_id = 1
+# text = Vamos al mar.
+1\tVamos\tir\tVERB\t_\t_\t0\troot\t_\t_
+2-3\tal\t_\t_\t_\t_\t_\t_\t_\t_
+2\ta\ta\tADP\t_\t_\t4\tcase\t_\t_
+3\tel\tel\tDET\t_\t_\t4\tdet\t_\t_
+4\tmar\tmar\tNOUN\t_\t_\t1\tobl\t_\tSpaceAfter=No
+5\t.\t.\tPUNCT\t_\t_\t4\tpunct\t4:punct\t_
+
+";
+
+    #[test]
+    fn read_multiword_modes() {
+        let skipped = read_conllu(CONLLU.as_bytes(), MultiwordMode::Skip).unwrap();
+        assert_eq!(skipped[0].toks, vec!["Vamos", "a", "el", "mar", "."]);
+        assert_eq!(skipped[0].annotations[3].upos.as_deref(), Some("NOUN"));
+        assert_eq!(skipped[0].annotations[3].head, Some(1));
+        let surface = read_conllu(CONLLU.as_bytes(), MultiwordMode::Surface).unwrap();
+        assert_eq!(surface[0].toks, vec!["Vamos", "al", "mar", "."]);
+        let text = surface[0].text().unwrap();
+        assert_eq!(text, "Vamos al mar.");
+        let aligned = surface[0].align(text, &RToksBuilder::new()).unwrap();
+        assert_eq!(aligned[2].0.s, 9);
+        assert_eq!(aligned[2].1.lemma.as_deref(), Some("mar"));
+    }
+
+    #[test]
+    fn write_alignment_in_misc() {
+        let sentences = read_conllu(CONLLU.as_bytes(), MultiwordMode::Surface).unwrap();
+        let links = vec![Reader::parse_links("0-0 2-1 2-3").unwrap()];
+        let mut out = vec![];
+        write_conllu(&mut out, &sentences, &links, LangKey::SOURCE).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("3\tmar\tmar\tNOUN\t_\t_\t1\tobl\t_\tSpaceAfter=No|Align=1,3\n"));
+        assert!(out.contains("4\t.\t.\tPUNCT\t_\t_\t3\tpunct\t3:punct\t_\n"));
+        let err = write_conllu(&mut vec![], &sentences, &[], LangKey::SOURCE).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<ConlluError>(),
+            Some(ConlluError::CountMismatch(1, 0))
+        ));
+        let reread = read_conllu(out.as_bytes(), MultiwordMode::Skip).unwrap();
+        assert_eq!(reread[0].toks, sentences[0].toks);
+    }
+}
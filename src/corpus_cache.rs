@@ -0,0 +1,343 @@
+use crate::bi_rtoks::BiRToks;
+use crate::bi_text::BiText;
+use crate::link::Link;
+use crate::offset_unit::OffsetUnit;
+use crate::rtok::RTok;
+use crate::textunit::Textunit;
+use memmap2::Mmap;
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{BufWriter, SeekFrom};
+
+const MAGIC: &[u8; 8] = b"PCTCACHE";
+const VERSION: u32 = 2;
+const HEADER_LEN: usize = 32;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum CacheError {
+        BadMagic { }
+        UnsupportedVersion(version: u32) {
+            display("Unsupported cache version {}", version)
+        }
+        UnknownOffsetUnit(tag: usize) {
+            display("Unknown offset unit {}", tag)
+        }
+        Corrupt(offset: usize) {
+            display("Corrupt cache at byte {}", offset)
+        }
+        OutOfRange(i: usize, len: usize) {
+            display("Textunit {} out of range, cache has {}", i, len)
+        }
+    }
+}
+
+fn write_u32<W: Write>(w: &mut W, v: usize) -> std::io::Result<()> {
+    let v = u32::try_from(v).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} does not fit in a u32", v),
+        )
+    })?;
+    w.write_all(&v.to_le_bytes())
+}
+
+fn write_str<W: Write>(w: &mut W, s: &str) -> std::io::Result<()> {
+    write_u32(w, s.len())?;
+    w.write_all(s.as_bytes())
+}
+
+fn write_rtoks<W: Write>(w: &mut W, rtoks: &[RTok]) -> std::io::Result<()> {
+    write_u32(w, rtoks.len())?;
+    for rtok in rtoks {
+        write_u32(w, rtok.s)?;
+        write_u32(w, rtok.e)?;
+        write_str(w, &rtok.text)?;
+    }
+    Ok(())
+}
+
+fn write_factors<W: Write>(w: &mut W, factors_list: &[Vec<String>]) -> std::io::Result<()> {
+    write_u32(w, factors_list.len())?;
+    for factors in factors_list {
+        write_u32(w, factors.len())?;
+        for factor in factors {
+            write_str(w, factor)?;
+        }
+    }
+    Ok(())
+}
+
+fn offset_unit_tag(offset_unit: OffsetUnit) -> usize {
+    match offset_unit {
+        OffsetUnit::Utf16 => 0,
+        OffsetUnit::Utf8 => 1,
+        OffsetUnit::CodePoint => 2,
+        OffsetUnit::Grapheme => 3,
+    }
+}
+
+fn write_textunit<W: Write>(w: &mut W, textunit: &Textunit) -> std::io::Result<()> {
+    write_str(w, &textunit.bi_text.source)?;
+    write_str(w, &textunit.bi_text.target)?;
+    write_u32(w, offset_unit_tag(textunit.bi_rtoks.offset_unit))?;
+    write_rtoks(w, &textunit.bi_rtoks.source)?;
+    write_rtoks(w, &textunit.bi_rtoks.target)?;
+    write_factors(w, &textunit.bi_rtoks.source_factors)?;
+    write_factors(w, &textunit.bi_rtoks.target_factors)?;
+    write_u32(w, textunit.links.len())?;
+    for link in &textunit.links {
+        write_u32(w, link.source)?;
+        write_u32(w, link.target)?;
+    }
+    Ok(())
+}
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], CacheError> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.data.len())
+            .ok_or(CacheError::Corrupt(self.pos))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<usize, CacheError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as usize)
+    }
+
+    fn u64(&mut self) -> Result<usize, CacheError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()) as usize)
+    }
+
+    fn string(&mut self) -> Result<String, CacheError> {
+        let pos = self.pos;
+        let len = self.u32()?;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|_| CacheError::Corrupt(pos))
+    }
+
+    fn rtoks(&mut self) -> Result<Vec<RTok>, CacheError> {
+        let n = self.u32()?;
+        let mut rtoks = Vec::with_capacity(n.min(self.data.len()));
+        for _ in 0..n {
+            let s = self.u32()?;
+            let e = self.u32()?;
+            let text = self.string()?;
+            rtoks.push(RTok { s, e, text });
+        }
+        Ok(rtoks)
+    }
+
+    fn factors(&mut self) -> Result<Vec<Vec<String>>, CacheError> {
+        let n = self.u32()?;
+        let mut factors_list = Vec::with_capacity(n.min(self.data.len()));
+        for _ in 0..n {
+            let m = self.u32()?;
+            let mut factors = Vec::with_capacity(m.min(self.data.len()));
+            for _ in 0..m {
+                factors.push(self.string()?);
+            }
+            factors_list.push(factors);
+        }
+        Ok(factors_list)
+    }
+
+    fn textunit(&mut self) -> Result<Textunit, CacheError> {
+        let source = self.string()?;
+        let target = self.string()?;
+        let offset_unit = match self.u32()? {
+            0 => OffsetUnit::Utf16,
+            1 => OffsetUnit::Utf8,
+            2 => OffsetUnit::CodePoint,
+            3 => OffsetUnit::Grapheme,
+            tag => return Err(CacheError::UnknownOffsetUnit(tag)),
+        };
+        let source_rtoks = self.rtoks()?;
+        let target_rtoks = self.rtoks()?;
+        let source_factors = self.factors()?;
+        let target_factors = self.factors()?;
+        let n = self.u32()?;
+        let mut links = Vec::with_capacity(n.min(self.data.len()));
+        for _ in 0..n {
+            let source = self.u32()?;
+            let target = self.u32()?;
+            links.push(Link { source, target });
+        }
+        Ok(Textunit {
+            bi_text: BiText { source, target },
+            bi_rtoks: BiRToks {
+                source: source_rtoks,
+                target: target_rtoks,
+                source_factors,
+                target_factors,
+                offset_unit,
+            },
+            links,
+        })
+    }
+}
+
+/// A corpus of aligned textunits in a binary file laid out as
+///
+/// ```text
+/// header: "PCTCACHE" version:u32 0:u32 count:u64 index_offset:u64
+/// records: source target offset_unit:u32 source_rtoks target_rtoks
+///          source_factors target_factors links
+/// index: count x offset:u64
+/// ```
+///
+/// All integers are little endian; strings are a u32 byte length followed
+/// by UTF-8. The file is memory mapped, so `get` only decodes one record.
+pub struct CorpusCache {
+    mmap: Mmap,
+    len: usize,
+    index_offset: usize,
+}
+
+impl CorpusCache {
+    pub fn create<'a, I>(path: &str, textunits: I) -> Result<usize, Box<dyn Error>>
+    where
+        I: IntoIterator<Item = &'a Textunit>,
+    {
+        let mut w = BufWriter::new(File::create(path)?);
+        w.write_all(&[0; HEADER_LEN])?;
+        let mut offsets = vec![];
+        let mut offset = HEADER_LEN as u64;
+        for textunit in textunits {
+            offsets.push(offset);
+            let mut record = vec![];
+            write_textunit(&mut record, textunit)?;
+            w.write_all(&record)?;
+            offset += record.len() as u64;
+        }
+        for record_offset in &offsets {
+            w.write_all(&record_offset.to_le_bytes())?;
+        }
+        w.seek(SeekFrom::Start(0))?;
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&0u32.to_le_bytes())?;
+        w.write_all(&(offsets.len() as u64).to_le_bytes())?;
+        w.write_all(&offset.to_le_bytes())?;
+        w.flush()?;
+        Ok(offsets.len())
+    }
+
+    pub fn open(path: &str) -> Result<CorpusCache, Box<dyn Error>> {
+        let f = File::open(path)?;
+        // The mapping is read only; the file must not be modified while open.
+        let mmap = unsafe { Mmap::map(&f)? };
+        let mut cursor = Cursor {
+            data: &mmap,
+            pos: 0,
+        };
+        if cursor.take(MAGIC.len())? != MAGIC {
+            return Err(Box::new(CacheError::BadMagic));
+        }
+        let version = cursor.u32()? as u32;
+        if version != VERSION {
+            return Err(Box::new(CacheError::UnsupportedVersion(version)));
+        }
+        cursor.u32()?;
+        let len = cursor.u64()?;
+        let index_offset = cursor.u64()?;
+        let index_end = len
+            .checked_mul(8)
+            .and_then(|index_len| index_len.checked_add(index_offset))
+            .ok_or(CacheError::Corrupt(index_offset))?;
+        if index_end > mmap.len() {
+            return Err(Box::new(CacheError::Corrupt(index_offset)));
+        }
+        Ok(CorpusCache {
+            mmap,
+            len,
+            index_offset,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, i: usize) -> Result<Textunit, CacheError> {
+        if i >= self.len {
+            return Err(CacheError::OutOfRange(i, self.len));
+        }
+        let mut index = Cursor {
+            data: &self.mmap,
+            pos: self.index_offset + i * 8,
+        };
+        let mut record = Cursor {
+            data: &self.mmap[..self.index_offset],
+            pos: index.u64()?,
+        };
+        record.textunit()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Result<Textunit, CacheError>> + '_ {
+        (0..self.len).map(move |i| self.get(i))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitext_reader::BitextRecord;
+    use crate::rtoks_builder::RToksBuilder;
+
+    #[test]
+    fn reject_values_above_u32() {
+        let mut out = vec![];
+        assert!(write_u32(&mut out, u32::MAX as usize).is_ok());
+        let err = write_u32(&mut out, u32::MAX as usize + 1).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn create_and_get_by_index() {
+        let rtoks_builder = RToksBuilder::new();
+        let mut textunits: Vec<_> = [
+            "A black cat\tแมวดำ\tA black cat\tแมว ดำ\t2-0 1-1",
+            "The dog\tหมา\tThe dog\tหมา\t1-0",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            BitextRecord::parse_tsv(line, &Default::default(), None)
+                .unwrap()
+                .into_textunit(&rtoks_builder, &rtoks_builder, i + 1)
+                .unwrap()
+        })
+        .collect();
+        // ASCII offsets are the same in code points
+        textunits[0].bi_rtoks.offset_unit = OffsetUnit::CodePoint;
+        textunits[1] = textunits[1].clone().with_factors(
+            vec![vec![String::from("DT")], vec![String::from("NN")]],
+            vec![vec![String::from("NOUN")]],
+        );
+        let path = std::env::temp_dir().join("parallel_corpus_tool_cache.bin");
+        let path = path.to_str().unwrap();
+        assert_eq!(CorpusCache::create(path, &textunits).unwrap(), 2);
+        let cache = CorpusCache::open(path).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get(1).unwrap(), textunits[1]);
+        assert_eq!(cache.get(0).unwrap(), textunits[0]);
+        assert!(cache.get(2).is_err());
+        drop(cache);
+        std::fs::remove_file(path).unwrap();
+    }
+}
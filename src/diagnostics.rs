@@ -0,0 +1,99 @@
+use crate::lang::LangKey;
+use crate::reader::ParsingError;
+use crate::rtoks_builder::CharAlignError;
+use crate::textunit::{InvalidLinks, TextunitLoadingError};
+use serde::Serialize;
+use std::io::Write;
+
+const CONTEXT_WIDTH: usize = 20;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum DiagnosticKind {
+    CannotMatchSomeToks,
+    CannotParseLinks,
+    LinkOutOfRange,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Diagnostic {
+    pub line_no: usize,
+    pub lang: Option<LangKey>,
+    pub kind: DiagnosticKind,
+    pub context: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Returns `None` for errors that are not specific to one textunit,
+    /// e.g. I/O errors, which should abort loading even in lenient mode.
+    pub fn from_loading_error(line_no: usize, err: &TextunitLoadingError) -> Option<Diagnostic> {
+        match err {
+            TextunitLoadingError::CannotAlignToks(lang, line_no, cause) => {
+                let context = cause
+                    .downcast_ref::<CharAlignError>()
+                    .map_or_else(String::new, |err| err.context(CONTEXT_WIDTH));
+                Some(Diagnostic {
+                    line_no: *line_no,
+                    lang: Some(*lang),
+                    kind: DiagnosticKind::CannotMatchSomeToks,
+                    context,
+                    message: cause.to_string(),
+                })
+            }
+            TextunitLoadingError::CannotLoadLinks(cause) if cause.is::<ParsingError>() => {
+                Some(Diagnostic {
+                    line_no,
+                    lang: None,
+                    kind: DiagnosticKind::CannotParseLinks,
+                    context: String::new(),
+                    message: cause.to_string(),
+                })
+            }
+            TextunitLoadingError::LinkOutOfRange(line_no, links) => {
+                Some(Self::from_invalid_links(&InvalidLinks {
+                    line_no: *line_no,
+                    links: links.clone(),
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn from_invalid_links(invalid_links: &InvalidLinks) -> Diagnostic {
+        Diagnostic {
+            line_no: invalid_links.line_no,
+            lang: None,
+            kind: DiagnosticKind::LinkOutOfRange,
+            context: invalid_links
+                .links
+                .iter()
+                .map(|link| format!("{}-{}", link.source, link.target))
+                .collect::<Vec<_>>()
+                .join(" "),
+            message: String::from("link index exceeds token count"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct Diagnostics {
+    pub entries: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn push(&mut self, diagnostic: Diagnostic) {
+        self.entries.push(diagnostic);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    pub fn write_json<W: Write>(&self, w: W) -> Result<(), serde_json::Error> {
+        serde_json::to_writer(w, self)
+    }
+}
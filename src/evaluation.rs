@@ -0,0 +1,133 @@
+use crate::gold_alignment::GoldAlignment;
+use crate::link::Link;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::error::Error;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum EvalError {
+        CountMismatch(hyps: usize, golds: usize) {
+            display("Textunit counts differ: hyps={} golds={}", hyps, golds)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct AlignmentCounts {
+    pub hyp: usize,
+    pub sure: usize,
+    pub hyp_and_sure: usize,
+    pub hyp_and_possible: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Scores {
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    pub aer: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Evaluation {
+    pub textunits: Vec<Scores>,
+    pub corpus: Scores,
+}
+
+fn ratio(n: usize, d: usize) -> f64 {
+    if d == 0 {
+        1.0
+    } else {
+        n as f64 / d as f64
+    }
+}
+
+impl AlignmentCounts {
+    pub fn new(hyp: &[Link], gold: &GoldAlignment) -> AlignmentCounts {
+        let hyp: HashSet<&Link> = hyp.iter().collect();
+        let sure: HashSet<&Link> = gold.sure.iter().collect();
+        let possible: HashSet<&Link> = gold.possible.iter().chain(gold.sure.iter()).collect();
+        AlignmentCounts {
+            hyp: hyp.len(),
+            sure: sure.len(),
+            hyp_and_sure: hyp.intersection(&sure).count(),
+            hyp_and_possible: hyp.intersection(&possible).count(),
+        }
+    }
+
+    pub fn add(&mut self, other: &AlignmentCounts) {
+        self.hyp += other.hyp;
+        self.sure += other.sure;
+        self.hyp_and_sure += other.hyp_and_sure;
+        self.hyp_and_possible += other.hyp_and_possible;
+    }
+
+    /// Precision is measured against possible links and recall against sure
+    /// links (Och and Ney, 2003). An empty denominator counts as a perfect
+    /// score.
+    pub fn scores(&self) -> Scores {
+        let precision = ratio(self.hyp_and_possible, self.hyp);
+        let recall = ratio(self.hyp_and_sure, self.sure);
+        let f1 = if precision + recall == 0.0 {
+            0.0
+        } else {
+            2.0 * precision * recall / (precision + recall)
+        };
+        let aer = 1.0
+            - ratio(
+                self.hyp_and_sure + self.hyp_and_possible,
+                self.hyp + self.sure,
+            );
+        Scores {
+            precision,
+            recall,
+            f1,
+            aer,
+        }
+    }
+}
+
+/// Corpus scores are computed from counts summed over all textunits.
+pub fn evaluate(hyps: &[Vec<Link>], golds: &[GoldAlignment]) -> Result<Evaluation, Box<dyn Error>> {
+    if hyps.len() != golds.len() {
+        return Err(From::from(EvalError::CountMismatch(
+            hyps.len(),
+            golds.len(),
+        )));
+    }
+    let mut corpus_counts = AlignmentCounts::default();
+    let mut textunits = vec![];
+    for (hyp, gold) in hyps.iter().zip(golds.iter()) {
+        let counts = AlignmentCounts::new(hyp, gold);
+        corpus_counts.add(&counts);
+        textunits.push(counts.scores());
+    }
+    Ok(Evaluation {
+        textunits,
+        corpus: corpus_counts.scores(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::Reader;
+
+    #[test]
+    fn evaluate_against_sure_and_possible() {
+        let hyps = vec![Reader::parse_links("0-0 1-1 2-3").unwrap()];
+        let golds = vec![Reader::parse_gold_links("0-0 1?1 2-2").unwrap()];
+        let err = evaluate(&hyps, &[]).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<EvalError>(),
+            Some(EvalError::CountMismatch(1, 0))
+        ));
+        let evaluation = evaluate(&hyps, &golds).unwrap();
+        let scores = evaluation.textunits[0];
+        assert!((scores.precision - 2.0 / 3.0).abs() < 1e-9);
+        assert!((scores.recall - 0.5).abs() < 1e-9);
+        assert!((scores.aer - (1.0 - 3.0 / 5.0)).abs() < 1e-9);
+        assert_eq!(evaluation.corpus, scores);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::lang::LangKey;
+use crate::reader::{ParsingError, Reader};
+use crate::rtoks_builder::RToksBuilder;
+use crate::textunit::{LinkPolicy, Textunit, TextunitIter};
+use std::error::Error;
+use std::io;
+use std::io::prelude::*;
+
+const SEPARATOR: &str = " ||| ";
+
+pub type BiToksList = (Vec<Vec<String>>, Vec<Vec<String>>);
+
+/// Writes one `source ||| target` line per sentence pair, the input format
+/// of fast_align and eflomal. Both lists must have the same length.
+pub fn write_fast_align<W: Write>(
+    w: &mut W,
+    source_toks_list: &[Vec<String>],
+    target_toks_list: &[Vec<String>],
+    lowercase: bool,
+) -> io::Result<()> {
+    if source_toks_list.len() != target_toks_list.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Source and target counts differ: {} {}",
+                source_toks_list.len(),
+                target_toks_list.len()
+            ),
+        ));
+    }
+    let join = |toks: &[String]| {
+        let line = toks.join(" ");
+        if lowercase {
+            line.to_lowercase()
+        } else {
+            line
+        }
+    };
+    for (source_toks, target_toks) in source_toks_list.iter().zip(target_toks_list.iter()) {
+        writeln!(w, "{}{}{}", join(source_toks), SEPARATOR, join(target_toks))?;
+    }
+    Ok(())
+}
+
+pub fn split_fast_align(line: &str) -> Result<(&str, &str), ParsingError> {
+    let mut sides = line.splitn(2, "|||");
+    let source = sides.next().ok_or(ParsingError::ParseFastAlign)?;
+    let target = sides.next().ok_or(ParsingError::ParseFastAlign)?;
+    Ok((source, target))
+}
+
+/// Parses both sides of a fast_align line, keeping only the surface factor
+/// of each token if `separator` is given.
+pub fn parse_fast_align(
+    line: &str,
+    separator: Option<&str>,
+) -> Result<(Vec<String>, Vec<String>), ParsingError> {
+    let (source, target) = split_fast_align(line)?;
+    Ok((
+        Reader::parse_surface_toks(source, separator),
+        Reader::parse_surface_toks(target, separator),
+    ))
+}
+
+/// Reads source and target token lists, honoring `textunit_offset` and
+/// `textunit_limit`.
+pub fn read_fast_align(reader: &Reader, path: &str) -> Result<BiToksList, Box<dyn Error>> {
+    let mut source_toks_list = vec![];
+    let mut target_toks_list = vec![];
+    for line in reader.open_lines(path)?.take(reader.config.textunit_limit) {
+        let (source_toks, target_toks) =
+            parse_fast_align(&line?, reader.config.factor_separator.as_deref())?;
+        source_toks_list.push(source_toks);
+        target_toks_list.push(target_toks);
+    }
+    Ok((source_toks_list, target_toks_list))
+}
+
+/// Loads textunits taking tokens from a fast_align file instead of the
+/// `tok_prefix` files; original lines and links come from `reader`. Fails
+/// if the files have different line counts.
+pub fn load_textunits(
+    reader: &Reader,
+    path: &str,
+    source_rtoks_builder: &RToksBuilder,
+    target_rtoks_builder: &RToksBuilder,
+    link_policy: LinkPolicy,
+) -> Result<Vec<Textunit>, Box<dyn Error>> {
+    Textunit::check_path_line_counts(vec![
+        reader.links_path(),
+        path.to_string(),
+        reader.lines_path(LangKey::SOURCE),
+        reader.lines_path(LangKey::TARGET),
+    ])?;
+    let mut textunits = vec![];
+    let iter =
+        TextunitIter::from_fast_align(reader, source_rtoks_builder, target_rtoks_builder, path)?;
+    for textunit in iter.link_policy(link_policy) {
+        textunits.push(textunit?);
+    }
+    Ok(textunits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, Langs};
+
+    #[test]
+    fn write_lowercased_and_parse() {
+        let source = vec![Reader::parse_toks("A Black cat")];
+        let target = vec![Reader::parse_toks("แมว ดำ")];
+        let mut out = vec![];
+        write_fast_align(&mut out, &source, &target, true).unwrap();
+        let line = String::from_utf8(out).unwrap();
+        assert_eq!(line, "a black cat ||| แมว ดำ\n");
+        let err = write_fast_align(&mut vec![], &source, &[], true).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        let (source_toks, target_toks) = parse_fast_align(line.trim_end(), None).unwrap();
+        assert_eq!(source_toks, vec!["a", "black", "cat"]);
+        assert_eq!(target_toks, vec!["แมว", "ดำ"]);
+        assert!(parse_fast_align("no separator", None).is_err());
+        let (source_toks, target_toks) =
+            parse_fast_align("black|JJ cat|NN ||| แมว|NOUN", Some("|")).unwrap();
+        assert_eq!(source_toks, vec!["black", "cat"]);
+        assert_eq!(target_toks, vec!["แมว"]);
+    }
+
+    #[test]
+    fn load_textunits_from_fast_align() {
+        let root = env!("CARGO_MANIFEST_DIR");
+        let langs = Langs {
+            source: String::from("en"),
+            target: String::from("th"),
+        };
+        let conf = Config {
+            corpus_dir: format!("{}/data", root),
+            tok_prefix: String::from("multi-toks"),
+            langs,
+            alignment_file_path: format!("{}/data/multi-links", root),
+            orig_prefix: String::from("multi-lines"),
+            ..Config::default()
+        };
+        let reader = Reader { config: conf };
+        let rtoks_builder = RToksBuilder::new();
+        let textunits = load_textunits(
+            &reader,
+            &format!("{}/data/multi.fast_align", root),
+            &rtoks_builder,
+            &rtoks_builder,
+            LinkPolicy::Keep,
+        )
+        .unwrap();
+        assert_eq!(
+            textunits,
+            Textunit::load(&reader, &rtoks_builder, &rtoks_builder).unwrap()
+        );
+        let shifted = Reader {
+            config: Config {
+                alignment_file_path: format!("{}/data/shifted-links", root),
+                ..reader.config.clone()
+            },
+        };
+        assert!(load_textunits(
+            &shifted,
+            &format!("{}/data/multi.fast_align", root),
+            &rtoks_builder,
+            &rtoks_builder,
+            LinkPolicy::Keep,
+        )
+        .is_err());
+    }
+}
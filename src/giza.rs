@@ -0,0 +1,134 @@
+use crate::link::Link;
+use crate::reader::{ParsingError, Reader};
+use regex::Regex;
+use std::error::Error;
+use std::io::prelude::*;
+
+lazy_static! {
+    static ref HEADER_RE: Regex =
+        Regex::new(r"^#\s*Sentence pair \((\d+)\).*alignment score\s*:\s*(\S+)").unwrap();
+    static ref WORD_RE: Regex = Regex::new(r"(\S+)\s+\(\{\s*((?:\d+\s+)*)\}\)").unwrap();
+}
+
+/// One sentence pair of a GIZA++ `*.A3.final` file. `source_toks` come
+/// from the `NULL ({ }) word ({ 1 2 })` line and `target_toks` from the
+/// plain line; links use 0-based indices with NULL excluded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GizaAlignment {
+    pub sentence_no: usize,
+    pub probability: f64,
+    pub source_toks: Vec<String>,
+    pub target_toks: Vec<String>,
+    pub links: Vec<Link>,
+}
+
+impl GizaAlignment {
+    pub fn parse(
+        header: &str,
+        target_line: &str,
+        source_line: &str,
+    ) -> Result<GizaAlignment, ParsingError> {
+        let caps = HEADER_RE
+            .captures(header)
+            .ok_or(ParsingError::ParseGizaHeader)?;
+        let sentence_no = caps[1].parse().map_err(|_| ParsingError::ParseNumber)?;
+        let probability = caps[2].parse().map_err(|_| ParsingError::ParseNumber)?;
+        let target_toks = Reader::parse_toks(target_line);
+        let mut source_toks = vec![];
+        let mut links = vec![];
+        let mut has_null = false;
+        for (i, caps) in WORD_RE.captures_iter(source_line).enumerate() {
+            has_null = true;
+            if i > 0 {
+                source_toks.push(caps[1].to_string());
+            }
+            for pos in caps[2].split_whitespace() {
+                let pos: usize = pos.parse().map_err(|_| ParsingError::ParseNumber)?;
+                if pos == 0 || pos > target_toks.len() {
+                    return Err(ParsingError::ParseGizaAlignment);
+                }
+                if i > 0 {
+                    links.push(Link {
+                        source: i - 1,
+                        target: pos - 1,
+                    });
+                }
+            }
+        }
+        if !has_null {
+            return Err(ParsingError::ParseGizaAlignment);
+        }
+        links.sort();
+        Ok(GizaAlignment {
+            sentence_no,
+            probability,
+            source_toks,
+            target_toks,
+            links,
+        })
+    }
+}
+
+pub struct GizaA3Iter<R: BufRead> {
+    lines: std::io::Lines<R>,
+}
+
+impl<R: BufRead> GizaA3Iter<R> {
+    pub fn new(r: R) -> GizaA3Iter<R> {
+        GizaA3Iter { lines: r.lines() }
+    }
+
+    fn next_alignment(&mut self, header: String) -> Result<GizaAlignment, Box<dyn Error>> {
+        let target_line = self
+            .lines
+            .next()
+            .ok_or(ParsingError::ParseGizaAlignment)??;
+        let source_line = self
+            .lines
+            .next()
+            .ok_or(ParsingError::ParseGizaAlignment)??;
+        Ok(GizaAlignment::parse(&header, &target_line, &source_line)?)
+    }
+}
+
+impl<R: BufRead> Iterator for GizaA3Iter<R> {
+    type Item = Result<GizaAlignment, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let header = match self.lines.next()? {
+                Ok(line) => line,
+                Err(err) => return Some(Err(Box::new(err))),
+            };
+            if !header.trim().is_empty() {
+                return Some(self.next_alignment(header));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A3: &str =
+        "# Sentence pair (1) source length 3 target length 3 alignment score : 0.000123
+the house is
+NULL ({ }) das ({ 1 }) haus ({ 2 }) ist ({ 3 })
+# Sentence pair (2) source length 2 target length 3 alignment score : 1.5e-05
+it is small
+NULL ({ 1 }) ist ({ 2 }) klein ({ 3 })
+";
+
+    #[test]
+    fn read_a3_final() {
+        let alignments: Vec<_> = GizaA3Iter::new(A3.as_bytes())
+            .map(|alignment| alignment.unwrap())
+            .collect();
+        assert_eq!(alignments.len(), 2);
+        assert_eq!(alignments[0].source_toks, vec!["das", "haus", "ist"]);
+        assert_eq!(alignments[0].target_toks, vec!["the", "house", "is"]);
+        assert_eq!(alignments[1].links, Reader::parse_links("0-1 1-2").unwrap());
+        assert!((alignments[1].probability - 1.5e-05).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,9 @@
+use crate::link::Link;
+
+/// `possible` holds only the links annotated as possible; evaluation treats
+/// every sure link as possible too.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct GoldAlignment {
+    pub sure: Vec<Link>,
+    pub possible: Vec<Link>,
+}
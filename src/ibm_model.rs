@@ -0,0 +1,448 @@
+use crate::link::Link;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::prelude::*;
+
+const NULL_WORD: &str = "<NULL>";
+const NULL: usize = 0;
+const MIN_PROB: f64 = 1e-12;
+
+#[derive(Debug, Clone, Default)]
+pub struct Vocab {
+    words: Vec<String>,
+    ids: HashMap<String, usize>,
+}
+
+impl Vocab {
+    fn with_null() -> Vocab {
+        let mut vocab = Vocab::default();
+        vocab.id_or_insert(NULL_WORD);
+        vocab
+    }
+
+    fn id_or_insert(&mut self, word: &str) -> usize {
+        if let Some(id) = self.ids.get(word) {
+            return *id;
+        }
+        let id = self.words.len();
+        self.words.push(word.to_string());
+        self.ids.insert(word.to_string(), id);
+        id
+    }
+
+    pub fn id(&self, word: &str) -> Option<usize> {
+        self.ids.get(word).copied()
+    }
+
+    pub fn word(&self, id: usize) -> &str {
+        &self.words[id]
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+// Source sentences start with NULL, so source position i + 1 is token i.
+type Corpus = Vec<(Vec<usize>, Vec<usize>)>;
+
+#[derive(Default)]
+struct Counts {
+    pair: HashMap<(usize, usize), f64>,
+    total: HashMap<usize, f64>,
+    align: HashMap<(usize, usize, usize, usize), f64>,
+    align_total: HashMap<(usize, usize, usize), f64>,
+}
+
+impl Counts {
+    fn merge(mut self, other: Counts) -> Counts {
+        for (k, v) in other.pair {
+            *self.pair.entry(k).or_insert(0.0) += v;
+        }
+        for (k, v) in other.total {
+            *self.total.entry(k).or_insert(0.0) += v;
+        }
+        for (k, v) in other.align {
+            *self.align.entry(k).or_insert(0.0) += v;
+        }
+        for (k, v) in other.align_total {
+            *self.align_total.entry(k).or_insert(0.0) += v;
+        }
+        self
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedModel {
+    t: Vec<(String, String, f64)>,
+    a: Vec<(usize, usize, usize, usize, f64)>,
+}
+
+/// Translation table t(target | source) with a NULL source word.
+#[derive(Debug, Clone, Default)]
+pub struct IbmModel1 {
+    pub source_vocab: Vocab,
+    pub target_vocab: Vocab,
+    t: HashMap<(usize, usize), f64>,
+}
+
+impl IbmModel1 {
+    fn build_corpus(
+        &mut self,
+        source_toks_list: &[Vec<String>],
+        target_toks_list: &[Vec<String>],
+    ) -> Corpus {
+        source_toks_list
+            .iter()
+            .zip(target_toks_list.iter())
+            .map(|(source_toks, target_toks)| {
+                let mut source = vec![NULL];
+                for tok in source_toks {
+                    source.push(self.source_vocab.id_or_insert(tok));
+                }
+                let target = target_toks
+                    .iter()
+                    .map(|tok| self.target_vocab.id_or_insert(tok))
+                    .collect();
+                (source, target)
+            })
+            .collect()
+    }
+
+    fn t(&self, source: usize, target: usize) -> f64 {
+        self.t.get(&(source, target)).copied().unwrap_or(MIN_PROB)
+    }
+
+    pub fn train(
+        source_toks_list: &[Vec<String>],
+        target_toks_list: &[Vec<String>],
+        iterations: usize,
+    ) -> IbmModel1 {
+        let mut model = IbmModel1 {
+            source_vocab: Vocab::with_null(),
+            target_vocab: Vocab::default(),
+            t: HashMap::new(),
+        };
+        let corpus = model.build_corpus(source_toks_list, target_toks_list);
+        let uniform = 1.0 / model.target_vocab.len().max(1) as f64;
+        for (source, target) in &corpus {
+            for &e in source {
+                for &f in target {
+                    model.t.insert((e, f), uniform);
+                }
+            }
+        }
+        for _ in 0..iterations {
+            model.em_step(&corpus);
+        }
+        model
+    }
+
+    fn em_step(&mut self, corpus: &Corpus) {
+        let counts = corpus
+            .par_iter()
+            .fold(Counts::default, |mut counts, (source, target)| {
+                for &f in target {
+                    let z: f64 = source.iter().map(|&e| self.t(e, f)).sum();
+                    for &e in source {
+                        let delta = self.t(e, f) / z;
+                        *counts.pair.entry((e, f)).or_insert(0.0) += delta;
+                        *counts.total.entry(e).or_insert(0.0) += delta;
+                    }
+                }
+                counts
+            })
+            .reduce(Counts::default, Counts::merge);
+        let Counts { pair, total, .. } = counts;
+        self.t = pair
+            .into_iter()
+            .map(|((e, f), count)| ((e, f), count / total[&e]))
+            .collect();
+    }
+
+    pub fn prob(&self, source: &str, target: &str) -> f64 {
+        match (self.source_vocab.id(source), self.target_vocab.id(target)) {
+            (Some(e), Some(f)) => self.t(e, f),
+            _ => MIN_PROB,
+        }
+    }
+
+    fn encode(&self, source_toks: &[String], target_toks: &[String]) -> (Vec<usize>, Vec<usize>) {
+        // Unknown words get an id outside the vocabulary so that they only
+        // match through MIN_PROB.
+        let unknown_source = self.source_vocab.len();
+        let unknown_target = self.target_vocab.len();
+        let mut source = vec![NULL];
+        source.extend(
+            source_toks
+                .iter()
+                .map(|tok| self.source_vocab.id(tok).unwrap_or(unknown_source)),
+        );
+        let target = target_toks
+            .iter()
+            .map(|tok| self.target_vocab.id(tok).unwrap_or(unknown_target))
+            .collect();
+        (source, target)
+    }
+
+    /// Viterbi alignment. Target words aligned to NULL get no link.
+    pub fn align(&self, source_toks: &[String], target_toks: &[String]) -> Vec<Link> {
+        let (source, target) = self.encode(source_toks, target_toks);
+        viterbi(&source, &target, |_, _, e, f| self.t(e, f))
+    }
+
+    pub fn align_corpus(
+        &self,
+        source_toks_list: &[Vec<String>],
+        target_toks_list: &[Vec<String>],
+    ) -> Vec<Vec<Link>> {
+        source_toks_list
+            .par_iter()
+            .zip(target_toks_list.par_iter())
+            .map(|(source_toks, target_toks)| self.align(source_toks, target_toks))
+            .collect()
+    }
+
+    fn saved_t(&self) -> Vec<(String, String, f64)> {
+        let mut t: Vec<_> = self
+            .t
+            .iter()
+            .map(|((e, f), p)| {
+                (
+                    self.source_vocab.word(*e).to_string(),
+                    self.target_vocab.word(*f).to_string(),
+                    *p,
+                )
+            })
+            .collect();
+        t.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(&b.1)));
+        t
+    }
+
+    fn from_saved_t(saved_t: &[(String, String, f64)]) -> IbmModel1 {
+        let mut model = IbmModel1 {
+            source_vocab: Vocab::with_null(),
+            target_vocab: Vocab::default(),
+            t: HashMap::new(),
+        };
+        for (source, target, p) in saved_t {
+            let e = model.source_vocab.id_or_insert(source);
+            let f = model.target_vocab.id_or_insert(target);
+            model.t.insert((e, f), *p);
+        }
+        model
+    }
+
+    pub fn save<W: Write>(&self, w: W) -> Result<(), Box<dyn Error>> {
+        let saved = SavedModel {
+            t: self.saved_t(),
+            a: vec![],
+        };
+        serde_json::to_writer(w, &saved)?;
+        Ok(())
+    }
+
+    pub fn load<R: Read>(r: R) -> Result<IbmModel1, Box<dyn Error>> {
+        let saved: SavedModel = serde_json::from_reader(r)?;
+        Ok(Self::from_saved_t(&saved.t))
+    }
+}
+
+fn viterbi<F>(source: &[usize], target: &[usize], score: F) -> Vec<Link>
+where
+    F: Fn(usize, usize, usize, usize) -> f64,
+{
+    let mut links = vec![];
+    for (j, &f) in target.iter().enumerate() {
+        let mut best_i = NULL;
+        let mut best_score = score(NULL, j, source[NULL], f);
+        for (i, &e) in source.iter().enumerate().skip(1) {
+            let s = score(i, j, e, f);
+            if s > best_score {
+                best_i = i;
+                best_score = s;
+            }
+        }
+        if best_i != NULL {
+            links.push(Link {
+                source: best_i - 1,
+                target: j,
+            });
+        }
+    }
+    links
+}
+
+/// Model 2 adds the alignment distribution a(i | j, l, m), where i is the
+/// source position (0 for NULL), j the target position, l the source length
+/// and m the target length.
+#[derive(Debug, Clone, Default)]
+pub struct IbmModel2 {
+    pub model1: IbmModel1,
+    a: HashMap<(usize, usize, usize, usize), f64>,
+}
+
+impl IbmModel2 {
+    /// Starts from a trained Model 1, as GIZA++ does.
+    pub fn train(
+        model1: IbmModel1,
+        source_toks_list: &[Vec<String>],
+        target_toks_list: &[Vec<String>],
+        iterations: usize,
+    ) -> IbmModel2 {
+        let mut model = IbmModel2 {
+            model1,
+            a: HashMap::new(),
+        };
+        let corpus = model
+            .model1
+            .build_corpus(source_toks_list, target_toks_list);
+        for _ in 0..iterations {
+            model.em_step(&corpus);
+        }
+        model
+    }
+
+    fn a(&self, i: usize, j: usize, l: usize, m: usize) -> f64 {
+        self.a
+            .get(&(i, j, l, m))
+            .copied()
+            .unwrap_or(1.0 / (l + 1) as f64)
+    }
+
+    fn em_step(&mut self, corpus: &Corpus) {
+        let counts = corpus
+            .par_iter()
+            .fold(Counts::default, |mut counts, (source, target)| {
+                let l = source.len() - 1;
+                let m = target.len();
+                for (j, &f) in target.iter().enumerate() {
+                    let z: f64 = source
+                        .iter()
+                        .enumerate()
+                        .map(|(i, &e)| self.model1.t(e, f) * self.a(i, j, l, m))
+                        .sum();
+                    for (i, &e) in source.iter().enumerate() {
+                        let delta = self.model1.t(e, f) * self.a(i, j, l, m) / z;
+                        *counts.pair.entry((e, f)).or_insert(0.0) += delta;
+                        *counts.total.entry(e).or_insert(0.0) += delta;
+                        *counts.align.entry((i, j, l, m)).or_insert(0.0) += delta;
+                        *counts.align_total.entry((j, l, m)).or_insert(0.0) += delta;
+                    }
+                }
+                counts
+            })
+            .reduce(Counts::default, Counts::merge);
+        let Counts {
+            pair,
+            total,
+            align,
+            align_total,
+        } = counts;
+        self.model1.t = pair
+            .into_iter()
+            .map(|((e, f), count)| ((e, f), count / total[&e]))
+            .collect();
+        self.a = align
+            .into_iter()
+            .map(|((i, j, l, m), count)| ((i, j, l, m), count / align_total[&(j, l, m)]))
+            .collect();
+    }
+
+    pub fn align(&self, source_toks: &[String], target_toks: &[String]) -> Vec<Link> {
+        let (source, target) = self.model1.encode(source_toks, target_toks);
+        let l = source.len() - 1;
+        let m = target.len();
+        viterbi(&source, &target, |i, j, e, f| {
+            self.model1.t(e, f) * self.a(i, j, l, m)
+        })
+    }
+
+    pub fn align_corpus(
+        &self,
+        source_toks_list: &[Vec<String>],
+        target_toks_list: &[Vec<String>],
+    ) -> Vec<Vec<Link>> {
+        source_toks_list
+            .par_iter()
+            .zip(target_toks_list.par_iter())
+            .map(|(source_toks, target_toks)| self.align(source_toks, target_toks))
+            .collect()
+    }
+
+    pub fn save<W: Write>(&self, w: W) -> Result<(), Box<dyn Error>> {
+        let mut a: Vec<_> = self
+            .a
+            .iter()
+            .map(|((i, j, l, m), p)| (*i, *j, *l, *m, *p))
+            .collect();
+        a.sort_by_key(|&(i, j, l, m, _)| (l, m, j, i));
+        let saved = SavedModel {
+            t: self.model1.saved_t(),
+            a,
+        };
+        serde_json::to_writer(w, &saved)?;
+        Ok(())
+    }
+
+    pub fn load<R: Read>(r: R) -> Result<IbmModel2, Box<dyn Error>> {
+        let saved: SavedModel = serde_json::from_reader(r)?;
+        Ok(IbmModel2 {
+            model1: IbmModel1::from_saved_t(&saved.t),
+            a: saved
+                .a
+                .into_iter()
+                .map(|(i, j, l, m, p)| ((i, j, l, m), p))
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::Reader;
+
+    fn corpus() -> (Vec<Vec<String>>, Vec<Vec<String>>) {
+        let source = vec!["das haus", "das buch", "ein buch"];
+        let target = vec!["the house", "the book", "a book"];
+        (
+            source.into_iter().map(Reader::parse_toks).collect(),
+            target.into_iter().map(Reader::parse_toks).collect(),
+        )
+    }
+
+    #[test]
+    fn train_model1() {
+        let (source, target) = corpus();
+        let model = IbmModel1::train(&source, &target, 10);
+        assert!(model.prob("das", "the") > 0.5);
+        assert!(model.prob("buch", "book") > 0.5);
+        let links = model.align(&source[0], &target[0]);
+        assert_eq!(Reader::parse_links("0-0 1-1").unwrap(), links);
+    }
+
+    #[test]
+    fn train_model2_and_reload() {
+        let (source, target) = corpus();
+        let model1 = IbmModel1::train(&source, &target, 5);
+        let model = IbmModel2::train(model1, &source, &target, 5);
+        let mut saved = vec![];
+        model.save(&mut saved).unwrap();
+        let loaded = IbmModel2::load(&saved[..]).unwrap();
+        assert_eq!(
+            loaded.align_corpus(&source, &target),
+            model.align_corpus(&source, &target)
+        );
+        assert_eq!(
+            loaded.align(&source[2], &target[2]),
+            Reader::parse_links("0-0 1-1").unwrap()
+        );
+    }
+}
@@ -1,13 +1,33 @@
+pub mod amphigram;
 pub mod bi_rtoks;
 pub mod bi_text;
+pub mod bitext_reader;
+pub mod compression;
 pub mod config;
+pub mod conllu;
+pub mod corpus_cache;
+pub mod diagnostics;
+pub mod evaluation;
+pub mod fast_align;
+pub mod giza;
+pub mod gold_alignment;
+pub mod ibm_model;
 pub mod lang;
+pub mod line_index;
 pub mod link;
+pub mod moses;
+pub mod offset_unit;
+pub mod phrase_extractor;
+pub mod phrase_pair_with_tok_index;
 pub mod reader;
 pub mod rtok;
 pub mod rtoks_builder;
+pub mod symmetrization;
 pub mod textunit;
+pub mod tmx;
 pub mod utf16;
+pub mod writer;
+pub mod xliff;
 
 #[macro_use]
 extern crate lazy_static;
@@ -0,0 +1,343 @@
+use crate::bi_text::BiText;
+use crate::compression::Compression;
+use crate::reader::Reader;
+use crate::rtoks_builder::RToksBuilder;
+use crate::textunit::{Textunit, TextunitLoadingError};
+use memmap2::Mmap;
+use std::convert::{TryFrom, TryInto};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter, SeekFrom};
+use std::time::UNIX_EPOCH;
+
+const MAGIC: &[u8; 8] = b"PCTLIDX2";
+const HEADER_LEN: usize = 32;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum LineIndexError {
+        Compressed(path: String) {
+            display("Cannot index compressed file {}", path)
+        }
+        CannotIndex(path: String) {
+            display("Cannot index {}", path)
+        }
+    }
+}
+
+fn data_stamp(path: &str) -> io::Result<(u64, u64)> {
+    let metadata = fs::metadata(path)?;
+    let mtime = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| u64::try_from(d.as_nanos()).unwrap_or(u64::MAX));
+    Ok((metadata.len(), mtime))
+}
+
+/// Byte offset of the start of each line of a plain text file, stored in a
+/// `<path>.idx` sidecar:
+///
+/// ```text
+/// "PCTLIDX2" data_len:u64 data_mtime:u64 count:u64 count x offset:u64
+/// ```
+///
+/// `data_mtime` is in nanoseconds since the Unix epoch.
+///
+/// The sidecar is ignored when the size or modification time of the data
+/// file no longer matches. Compressed files cannot be indexed.
+pub struct LineIndex {
+    mmap: Mmap,
+    len: usize,
+    data_len: u64,
+}
+
+impl LineIndex {
+    pub fn sidecar_path(path: &str) -> String {
+        format!("{}.idx", path)
+    }
+
+    pub fn build(path: &str) -> Result<(), Box<dyn Error>> {
+        let mut reader = BufReader::new(File::open(path)?);
+        if Compression::from_magic(reader.fill_buf()?).is_some() {
+            return Err(From::from(LineIndexError::Compressed(path.to_string())));
+        }
+        let (data_len, mtime) = data_stamp(path)?;
+        let mut w = BufWriter::new(File::create(Self::sidecar_path(path))?);
+        w.write_all(&[0; HEADER_LEN])?;
+        let mut buf = vec![];
+        let mut offset = 0u64;
+        let mut count = 0u64;
+        loop {
+            let n = reader.read_until(b'\n', &mut buf)?;
+            if n == 0 {
+                break;
+            }
+            w.write_all(&offset.to_le_bytes())?;
+            offset += n as u64;
+            count += 1;
+            buf.clear();
+        }
+        w.seek(SeekFrom::Start(0))?;
+        w.write_all(MAGIC)?;
+        w.write_all(&data_len.to_le_bytes())?;
+        w.write_all(&mtime.to_le_bytes())?;
+        w.write_all(&count.to_le_bytes())?;
+        w.flush()?;
+        Ok(())
+    }
+
+    /// Returns `None` if there is no up-to-date sidecar for `path`.
+    pub fn open(path: &str) -> Result<Option<LineIndex>, Box<dyn Error>> {
+        let f = match File::open(Self::sidecar_path(path)) {
+            Ok(f) => f,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(Box::new(err)),
+        };
+        // The sidecar is only read; a concurrent rebuild would be detected
+        // by the header check on the next open.
+        let mmap = unsafe { Mmap::map(&f)? };
+        if mmap.len() < HEADER_LEN || &mmap[..8] != MAGIC {
+            return Ok(None);
+        }
+        let field = |i: usize| u64::from_le_bytes(mmap[8 * i..8 * (i + 1)].try_into().unwrap());
+        let (data_len, mtime) = data_stamp(path)?;
+        let len = field(3) as usize;
+        let expected_len = len.checked_mul(8).and_then(|n| n.checked_add(HEADER_LEN));
+        if field(1) != data_len || field(2) != mtime || expected_len != Some(mmap.len()) {
+            return Ok(None);
+        }
+        Ok(Some(LineIndex {
+            mmap,
+            len,
+            data_len,
+        }))
+    }
+
+    pub fn open_or_build(path: &str) -> Result<LineIndex, Box<dyn Error>> {
+        if let Some(index) = Self::open(path)? {
+            return Ok(index);
+        }
+        Self::build(path)?;
+        Self::open(path)?.ok_or_else(|| From::from(LineIndexError::CannotIndex(path.to_string())))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Byte offset of line `i`; the file length if `i` is past the end.
+    pub fn offset(&self, i: usize) -> u64 {
+        if i >= self.len {
+            return self.data_len;
+        }
+        let s = HEADER_LEN + i * 8;
+        u64::from_le_bytes(self.mmap[s..s + 8].try_into().unwrap())
+    }
+
+    pub fn read_line(&self, path: &str, i: usize) -> Result<Option<String>, Box<dyn Error>> {
+        if i >= self.len {
+            return Ok(None);
+        }
+        let mut f = File::open(path)?;
+        f.seek(SeekFrom::Start(self.offset(i)))?;
+        let mut line = String::new();
+        BufReader::new(f).read_line(&mut line)?;
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+}
+
+/// Random access to the textunits of a multi-file corpus through the line
+/// indexes of its five input files.
+pub struct IndexedCorpus {
+    paths: Vec<String>,
+    indexes: Vec<LineIndex>,
+    factor_separator: Option<String>,
+}
+
+impl IndexedCorpus {
+    pub fn new(reader: &Reader) -> Result<IndexedCorpus, Box<dyn Error>> {
+        let paths = reader.input_paths();
+        let mut indexes = vec![];
+        for path in &paths {
+            indexes.push(LineIndex::open_or_build(path)?);
+        }
+        Ok(IndexedCorpus {
+            paths,
+            indexes,
+            factor_separator: reader.config.factor_separator.clone(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.indexes.iter().map(LineIndex::len).min().unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets the textunit on 0-based line `i`.
+    pub fn get(
+        &self,
+        i: usize,
+        source_rtoks_builder: &RToksBuilder,
+        target_rtoks_builder: &RToksBuilder,
+    ) -> Result<Option<Textunit>, Box<dyn Error>> {
+        let mut lines = vec![];
+        for (path, index) in self.paths.iter().zip(self.indexes.iter()) {
+            match index.read_line(path, i)? {
+                Some(line) => lines.push(line),
+                None => return Ok(None),
+            }
+        }
+        let line_no = i + 1;
+        let links = Reader::parse_links(&lines[0])
+            .map_err(|err| TextunitLoadingError::CannotLoadLinks(Box::new(err)))?;
+        let separator = self.factor_separator.as_deref();
+        let (source_toks, source_factors) = Reader::parse_toks_with_factors(&lines[1], separator);
+        let (target_toks, target_factors) = Reader::parse_toks_with_factors(&lines[2], separator);
+        let bi_text = BiText {
+            source: lines.swap_remove(3),
+            target: lines.swap_remove(3),
+        };
+        let textunit = Textunit::align_with_builders(
+            source_rtoks_builder,
+            target_rtoks_builder,
+            line_no,
+            bi_text,
+            &source_toks,
+            &target_toks,
+            links,
+        )?
+        .with_factors(source_factors, target_factors);
+        Ok(Some(textunit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, Langs};
+    use crate::lang::LangKey;
+
+    #[test]
+    fn detect_stale_sidecar() {
+        let path = std::env::temp_dir().join("parallel_corpus_tool_stale_index.txt");
+        fs::write(&path, "ABC\nDEF\n").unwrap();
+        let path_str = path.to_str().unwrap();
+        LineIndex::build(path_str).unwrap();
+        assert!(LineIndex::open(path_str).unwrap().is_some());
+        // same size, modified less than a second later
+        let mtime = fs::metadata(&path).unwrap().modified().unwrap();
+        fs::write(&path, "ABCD\nEF\n").unwrap();
+        let f = File::options().write(true).open(&path).unwrap();
+        f.set_modified(mtime + std::time::Duration::from_millis(1))
+            .unwrap();
+        assert!(LineIndex::open(path_str).unwrap().is_none());
+        // a corrupt count must not overflow
+        LineIndex::build(path_str).unwrap();
+        let sidecar = LineIndex::sidecar_path(path_str);
+        let mut bytes = fs::read(&sidecar).unwrap();
+        bytes[24..32].copy_from_slice(&u64::MAX.to_le_bytes());
+        fs::write(&sidecar, bytes).unwrap();
+        assert!(LineIndex::open(path_str).unwrap().is_none());
+        fs::remove_file(&sidecar).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn refuse_to_index_compressed_file() {
+        let path = std::env::temp_dir().join("parallel_corpus_tool_compressed_index.gz");
+        fs::write(&path, [0x1f, 0x8b, 0x08]).unwrap();
+        let err = LineIndex::build(path.to_str().unwrap()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LineIndexError>(),
+            Some(LineIndexError::Compressed(_))
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn seek_with_line_index() {
+        let root = env!("CARGO_MANIFEST_DIR");
+        let dir = std::env::temp_dir().join("parallel_corpus_tool_line_index");
+        fs::create_dir_all(&dir).unwrap();
+        for name in &[
+            "multi-toks.en",
+            "multi-toks.th",
+            "multi-lines.en",
+            "multi-lines.th",
+            "multi-links",
+            "factored-toks.en",
+            "factored-toks.th",
+        ] {
+            fs::copy(format!("{}/data/{}", root, name), dir.join(name)).unwrap();
+        }
+        let dir = dir.to_str().unwrap();
+        let langs = Langs {
+            source: String::from("en"),
+            target: String::from("th"),
+        };
+        let conf = Config {
+            corpus_dir: dir.to_string(),
+            tok_prefix: String::from("multi-toks"),
+            langs,
+            alignment_file_path: format!("{}/multi-links", dir),
+            orig_prefix: String::from("multi-lines"),
+            textunit_offset: 2,
+            ..Config::default()
+        };
+        let reader = Reader { config: conf };
+        reader.build_line_indexes().unwrap();
+        let path = format!("{}/multi-lines.en", dir);
+        let index = LineIndex::open(&path).unwrap().unwrap();
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.offset(1), 12);
+        assert_eq!(
+            reader.read_lines(LangKey::SOURCE).unwrap(),
+            vec![String::from("Hello world!")]
+        );
+        let rtoks_builder = RToksBuilder::new();
+        let corpus = IndexedCorpus::new(&reader).unwrap();
+        assert_eq!(corpus.len(), 3);
+        let textunit = corpus
+            .get(1, &rtoks_builder, &rtoks_builder)
+            .unwrap()
+            .unwrap();
+        assert_eq!(textunit.bi_text.target, "หมา");
+        assert!(corpus
+            .get(3, &rtoks_builder, &rtoks_builder)
+            .unwrap()
+            .is_none());
+        let factored = Reader {
+            config: Config {
+                tok_prefix: String::from("factored-toks"),
+                factor_separator: Some(String::from("|")),
+                ..reader.config.clone()
+            },
+        };
+        let corpus = IndexedCorpus::new(&factored).unwrap();
+        let factored_textunit = corpus
+            .get(1, &rtoks_builder, &rtoks_builder)
+            .unwrap()
+            .unwrap();
+        assert_eq!(factored_textunit.bi_rtoks.source, textunit.bi_rtoks.source);
+        assert_eq!(
+            factored_textunit.bi_rtoks.target_factors,
+            vec![vec!["NOUN", "หมา"]]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
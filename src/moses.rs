@@ -0,0 +1,156 @@
+use crate::rtoks_builder::StrMod;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::io::prelude::*;
+
+/// Characters escaped by Moses' `escape-special-chars.perl`. `&` comes first
+/// so that escaping does not touch the entities it produces.
+const ENTITIES: [(&str, &str); 8] = [
+    ("&", "&amp;"),
+    ("|", "&#124;"),
+    ("<", "&lt;"),
+    (">", "&gt;"),
+    ("'", "&apos;"),
+    ("\"", "&quot;"),
+    ("[", "&#91;"),
+    ("]", "&#93;"),
+];
+
+const HYPHEN: (&str, &str) = ("-", "@-@");
+
+/// Escaped space used by some Moses pipelines inside tokens.
+const SPACE: (&str, &str) = (" ", "\u{F112}");
+
+/// Which Moses escapes are in effect for one language.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(default)]
+pub struct MosesEscaping {
+    pub entities: bool,
+    /// `@-@` markers from `tokenizer.perl -a`.
+    pub aggressive_hyphens: bool,
+}
+
+impl Default for MosesEscaping {
+    fn default() -> MosesEscaping {
+        MosesEscaping {
+            entities: true,
+            aggressive_hyphens: true,
+        }
+    }
+}
+
+impl MosesEscaping {
+    /// Escapes a token for a Moses token file. Hyphens are left alone since
+    /// whether they were split off a word is not known here.
+    pub fn escape(&self, tok: &str) -> String {
+        let mut tok = tok.to_string();
+        if self.entities {
+            for (c, entity) in ENTITIES.iter() {
+                tok = tok.replace(c, entity);
+            }
+        }
+        tok
+    }
+
+    pub fn unescape(&self, tok: &str) -> String {
+        let mut tok = tok.replace(SPACE.1, SPACE.0);
+        if self.aggressive_hyphens {
+            tok = tok.replace(HYPHEN.1, HYPHEN.0);
+        }
+        if self.entities {
+            for (c, entity) in ENTITIES.iter().rev() {
+                tok = tok.replace(entity, c);
+            }
+        }
+        tok
+    }
+}
+
+pub struct MosesStrMod {
+    pub escaping: MosesEscaping,
+}
+
+impl StrMod for MosesStrMod {
+    fn mod_tok(&self, s: &str) -> String {
+        self.escaping.unescape(s)
+    }
+}
+
+/// Writes one line of space separated, escaped tokens per token list.
+pub fn write_moses_toks<W: Write>(
+    w: &mut W,
+    toks_list: &[Vec<String>],
+    escaping: &MosesEscaping,
+) -> io::Result<()> {
+    for toks in toks_list {
+        let toks: Vec<_> = toks.iter().map(|tok| escaping.escape(tok)).collect();
+        writeln!(w, "{}", toks.join(" "))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::reader::Reader;
+    use crate::rtoks_builder::RToksBuilder;
+
+    #[test]
+    fn escape_round_trip() {
+        let escaping = MosesEscaping::default();
+        let tok = "<a href=\"x\">[1]|'&amp;'";
+        assert_eq!(
+            escaping.escape(tok),
+            "&lt;a href=&quot;x&quot;&gt;&#91;1&#93;&#124;&apos;&amp;amp;&apos;"
+        );
+        assert_eq!(escaping.unescape(&escaping.escape(tok)), tok);
+    }
+
+    #[test]
+    fn match_escaped_toks() {
+        let toks = Reader::parse_toks("a &lt; b &#124; well @-@ known");
+        let text = "a < b | well-known";
+        let rtoks = RToksBuilder::new().align_text_toks(text, &toks).unwrap();
+        assert_eq!(rtoks[5].text, "-");
+        assert_eq!(rtoks[5].s, 12);
+        let builder = RToksBuilder::with_str_mods(vec![Box::new(MosesStrMod {
+            escaping: MosesEscaping {
+                entities: true,
+                aggressive_hyphens: false,
+            },
+        })]);
+        assert!(builder.align_text_toks(text, &toks).is_err());
+        let mut out = vec![];
+        let toks_list = vec![vec![
+            String::from("a"),
+            String::from("<"),
+            String::from("b"),
+        ]];
+        write_moses_toks(&mut out, &toks_list, &MosesEscaping::default()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "a &lt; b\n");
+    }
+
+    #[test]
+    fn keep_entities_if_disabled() {
+        let toks = Reader::parse_toks("R&amp;D");
+        let mut config = Config::default();
+        let builder = RToksBuilder::from_config_for_lang(&config, "en");
+        assert_eq!(
+            builder.align_text_toks("R&D", &toks).unwrap()[0].text,
+            "R&D"
+        );
+        config.moses_escaping.insert(
+            String::from("en"),
+            MosesEscaping {
+                entities: false,
+                aggressive_hyphens: true,
+            },
+        );
+        let builder = RToksBuilder::from_config_for_lang(&config, "en");
+        assert!(builder.align_text_toks("R&D", &toks).is_err());
+        let rtoks = builder.align_text_toks("R&amp;D", &toks).unwrap();
+        assert_eq!(rtoks[0].text, "R&amp;D");
+        assert_eq!(rtoks[0].e, 7);
+    }
+}
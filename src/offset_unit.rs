@@ -0,0 +1,202 @@
+use crate::rtok::RTok;
+use serde::{Deserialize, Serialize};
+use unicode_segmentation::UnicodeSegmentation;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum OffsetError {
+        InvalidOffset(offset: usize, unit: OffsetUnit) {
+            display("Offset {} is not a {:?} boundary", offset, unit)
+        }
+    }
+}
+
+/// The unit of `RTok.s` and `RTok.e`.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum OffsetUnit {
+    #[default]
+    Utf16,
+    Utf8,
+    CodePoint,
+    Grapheme,
+}
+
+/// Offsets of one character boundary in every unit. `grapheme` counts the
+/// grapheme clusters starting before the boundary, so inside a cluster it
+/// is one past the cluster's index.
+struct Boundary {
+    utf16: usize,
+    utf8: usize,
+    code_point: usize,
+    grapheme: usize,
+    at_grapheme: bool,
+}
+
+impl Boundary {
+    fn get(&self, unit: OffsetUnit) -> usize {
+        match unit {
+            OffsetUnit::Utf16 => self.utf16,
+            OffsetUnit::Utf8 => self.utf8,
+            OffsetUnit::CodePoint => self.code_point,
+            OffsetUnit::Grapheme => self.grapheme,
+        }
+    }
+}
+
+/// Converts offsets of one text between units.
+pub struct OffsetConverter {
+    boundaries: Vec<Boundary>,
+}
+
+impl OffsetConverter {
+    pub fn new(text: &str) -> OffsetConverter {
+        let mut grapheme_starts = text.grapheme_indices(true).map(|(i, _)| i).peekable();
+        let mut boundaries = vec![];
+        let mut utf16 = 0;
+        let mut grapheme = 0;
+        let chars = text
+            .char_indices()
+            .map(|(i, c)| (i, Some(c)))
+            .chain(std::iter::once((text.len(), None)));
+        for (code_point, (utf8, c)) in chars.enumerate() {
+            let at_grapheme = grapheme_starts.peek() == Some(&utf8) || c.is_none();
+            boundaries.push(Boundary {
+                utf16,
+                utf8,
+                code_point,
+                grapheme,
+                at_grapheme,
+            });
+            if at_grapheme && c.is_some() {
+                grapheme_starts.next();
+                grapheme += 1;
+            }
+            utf16 += c.map_or(0, char::len_utf16);
+        }
+        OffsetConverter { boundaries }
+    }
+
+    fn boundary(&self, offset: usize, unit: OffsetUnit) -> Result<&Boundary, OffsetError> {
+        let i = self
+            .boundaries
+            .partition_point(|boundary| boundary.get(unit) < offset);
+        // the grapheme count repeats inside a cluster; only its start matches
+        self.boundaries[i..]
+            .iter()
+            .take_while(|boundary| boundary.get(unit) == offset)
+            .find(|boundary| unit != OffsetUnit::Grapheme || boundary.at_grapheme)
+            .ok_or(OffsetError::InvalidOffset(offset, unit))
+    }
+
+    /// Converts `offset`; inside a grapheme cluster the result is rounded
+    /// down to the start of the cluster, or up to its end if `is_end`.
+    pub fn convert(
+        &self,
+        offset: usize,
+        from: OffsetUnit,
+        to: OffsetUnit,
+        is_end: bool,
+    ) -> Result<usize, OffsetError> {
+        let boundary = self.boundary(offset, from)?;
+        if to == OffsetUnit::Grapheme && !boundary.at_grapheme && !is_end {
+            Ok(boundary.grapheme - 1)
+        } else {
+            Ok(boundary.get(to))
+        }
+    }
+
+    pub fn convert_rtoks(
+        &self,
+        rtoks: &[RTok],
+        from: OffsetUnit,
+        to: OffsetUnit,
+    ) -> Result<Vec<RTok>, OffsetError> {
+        rtoks
+            .iter()
+            .map(|rtok| {
+                Ok(RTok {
+                    s: self.convert(rtok.s, from, to, false)?,
+                    e: self.convert(rtok.e, from, to, true)?,
+                    text: rtok.text.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Converts the offsets of `rtoks` aligned to `text` from one unit to another.
+pub fn convert_rtoks(
+    text: &str,
+    rtoks: &[RTok],
+    from: OffsetUnit,
+    to: OffsetUnit,
+) -> Result<Vec<RTok>, OffsetError> {
+    OffsetConverter::new(text).convert_rtoks(rtoks, from, to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rtoks_builder::RToksBuilder;
+
+    #[test]
+    fn convert_between_units() {
+        // "น้ำ" is one grapheme of three code points, "😀" two UTF-16 units
+        let text = "น้ำ 😀 cat";
+        let toks = vec![String::from("น้ำ"), String::from("😀"), String::from("cat")];
+        let rtoks = RToksBuilder::new().align_text_toks(text, &toks).unwrap();
+        let spans = |unit| {
+            convert_rtoks(text, &rtoks, OffsetUnit::Utf16, unit)
+                .unwrap()
+                .iter()
+                .map(|rtok| (rtok.s, rtok.e))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(spans(OffsetUnit::Utf16), vec![(0, 3), (4, 6), (7, 10)]);
+        assert_eq!(spans(OffsetUnit::Utf8), vec![(0, 9), (10, 14), (15, 18)]);
+        assert_eq!(spans(OffsetUnit::CodePoint), vec![(0, 3), (4, 5), (6, 9)]);
+        assert_eq!(spans(OffsetUnit::Grapheme), vec![(0, 1), (2, 3), (4, 7)]);
+        let graphemes =
+            convert_rtoks(text, &rtoks, OffsetUnit::Utf16, OffsetUnit::Grapheme).unwrap();
+        let back = convert_rtoks(text, &graphemes, OffsetUnit::Grapheme, OffsetUnit::Utf8).unwrap();
+        assert_eq!((back[1].s, back[1].e), (10, 14));
+    }
+
+    #[test]
+    fn convert_from_grapheme() {
+        let text = "น้ำ cat";
+        let converter = OffsetConverter::new(text);
+        let convert = |offset, to| {
+            converter
+                .convert(offset, OffsetUnit::Grapheme, to, false)
+                .unwrap()
+        };
+        assert_eq!(convert(1, OffsetUnit::Utf8), 9);
+        assert_eq!(convert(2, OffsetUnit::Utf16), 4);
+        let rtoks = vec![RTok {
+            s: 0,
+            e: 1,
+            text: String::from("น้ำ"),
+        }];
+        let utf16 = convert_rtoks(text, &rtoks, OffsetUnit::Grapheme, OffsetUnit::Utf16).unwrap();
+        assert_eq!((utf16[0].s, utf16[0].e), (0, 3));
+        let back = convert_rtoks(text, &utf16, OffsetUnit::Utf16, OffsetUnit::Grapheme).unwrap();
+        assert_eq!(back, rtoks);
+    }
+
+    #[test]
+    fn round_inside_grapheme() {
+        let converter = OffsetConverter::new("น้ำ");
+        let convert = |offset, is_end| {
+            converter
+                .convert(offset, OffsetUnit::CodePoint, OffsetUnit::Grapheme, is_end)
+                .unwrap()
+        };
+        assert_eq!(convert(1, false), 0);
+        assert_eq!(convert(1, true), 1);
+        assert!(converter
+            .convert(1, OffsetUnit::Utf8, OffsetUnit::Utf16, false)
+            .is_err());
+    }
+}
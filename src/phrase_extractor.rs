@@ -0,0 +1,178 @@
+use crate::link::Link;
+use crate::phrase_pair_with_tok_index::{PhrasePairWithTokIndex, PhraseRangeWithTokIndex};
+use crate::textunit::Textunit;
+
+pub struct PhraseExtractor {
+    pub max_phrase_len: usize,
+}
+
+impl Default for PhraseExtractor {
+    fn default() -> PhraseExtractor {
+        PhraseExtractor { max_phrase_len: 7 }
+    }
+}
+
+impl PhraseExtractor {
+    pub fn new(max_phrase_len: usize) -> PhraseExtractor {
+        PhraseExtractor { max_phrase_len }
+    }
+
+    pub fn extract(&self, textunit: &Textunit) -> Vec<PhrasePairWithTokIndex> {
+        self.extract_links(
+            &textunit.links,
+            textunit.bi_rtoks.source.len(),
+            textunit.bi_rtoks.target.len(),
+        )
+    }
+
+    /// Extracts every phrase pair consistent with `links` (Koehn et al., 2003).
+    /// Ranges are half-open token indices. Unaligned target words at the
+    /// boundaries of a phrase are included in additional pairs.
+    pub fn extract_links(
+        &self,
+        links: &[Link],
+        source_len: usize,
+        target_len: usize,
+    ) -> Vec<PhrasePairWithTokIndex> {
+        let links: Vec<&Link> = links
+            .iter()
+            .filter(|link| link.source < source_len && link.target < target_len)
+            .collect();
+        let mut target_aligned = vec![false; target_len];
+        for link in &links {
+            target_aligned[link.target] = true;
+        }
+        let mut phrase_pairs = vec![];
+        for source_s in 0..source_len {
+            let source_e_max = (source_s + self.max_phrase_len).min(source_len);
+            for source_e in (source_s + 1)..=source_e_max {
+                let target_span = links
+                    .iter()
+                    .filter(|link| link.source >= source_s && link.source < source_e)
+                    .fold(None, |span: Option<(usize, usize)>, link| match span {
+                        None => Some((link.target, link.target + 1)),
+                        Some((s, e)) => Some((s.min(link.target), e.max(link.target + 1))),
+                    });
+                if let Some((target_s, target_e)) = target_span {
+                    self.extract_span(
+                        &links,
+                        &target_aligned,
+                        (source_s, source_e),
+                        (target_s, target_e),
+                        &mut phrase_pairs,
+                    );
+                }
+            }
+        }
+        phrase_pairs
+    }
+
+    fn extract_span(
+        &self,
+        links: &[&Link],
+        target_aligned: &[bool],
+        (source_s, source_e): (usize, usize),
+        (target_s, target_e): (usize, usize),
+        phrase_pairs: &mut Vec<PhrasePairWithTokIndex>,
+    ) {
+        let is_consistent = links.iter().all(|link| {
+            link.target < target_s
+                || link.target >= target_e
+                || (link.source >= source_s && link.source < source_e)
+        });
+        if !is_consistent || target_e - target_s > self.max_phrase_len {
+            return;
+        }
+        let mut s = target_s;
+        loop {
+            let mut e = target_e;
+            loop {
+                if e - s > self.max_phrase_len {
+                    break;
+                }
+                phrase_pairs.push(PhrasePairWithTokIndex {
+                    source: PhraseRangeWithTokIndex {
+                        s: source_s,
+                        e: source_e,
+                    },
+                    target: PhraseRangeWithTokIndex { s, e },
+                });
+                if e == target_aligned.len() || target_aligned[e] {
+                    break;
+                }
+                e += 1;
+            }
+            if s == 0 || target_aligned[s - 1] {
+                break;
+            }
+            s -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(source: (usize, usize), target: (usize, usize)) -> PhrasePairWithTokIndex {
+        PhrasePairWithTokIndex {
+            source: PhraseRangeWithTokIndex {
+                s: source.0,
+                e: source.1,
+            },
+            target: PhraseRangeWithTokIndex {
+                s: target.0,
+                e: target.1,
+            },
+        }
+    }
+
+    #[test]
+    fn extract_with_unaligned_boundary() {
+        // source: A black cat / target: แมว ดำ ?
+        let links = vec![
+            Link {
+                source: 2,
+                target: 0,
+            },
+            Link {
+                source: 1,
+                target: 1,
+            },
+        ];
+        let phrase_pairs = PhraseExtractor::new(3).extract_links(&links, 3, 3);
+        assert_eq!(
+            phrase_pairs,
+            vec![
+                pair((0, 2), (1, 2)),
+                pair((0, 2), (1, 3)),
+                pair((0, 3), (0, 2)),
+                pair((0, 3), (0, 3)),
+                pair((1, 2), (1, 2)),
+                pair((1, 2), (1, 3)),
+                pair((1, 3), (0, 2)),
+                pair((1, 3), (0, 3)),
+                pair((2, 3), (0, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn extract_respects_max_phrase_len() {
+        let links: Vec<Link> = (0..3)
+            .map(|i| Link {
+                source: i,
+                target: i,
+            })
+            .collect();
+        let phrase_pairs = PhraseExtractor::new(1).extract_links(&links, 3, 3);
+        assert_eq!(
+            phrase_pairs,
+            vec![
+                pair((0, 1), (0, 1)),
+                pair((1, 2), (1, 2)),
+                pair((2, 3), (2, 3)),
+            ]
+        );
+    }
+}
@@ -1,10 +1,10 @@
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PhraseRangeWithTokIndex {
     pub s: usize,
     pub e: usize,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PhrasePairWithTokIndex {
     pub source: PhraseRangeWithTokIndex,
     pub target: PhraseRangeWithTokIndex,
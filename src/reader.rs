@@ -1,16 +1,26 @@
+use crate::compression::open_input;
 use crate::config::Config;
+use crate::giza::{GizaA3Iter, GizaAlignment};
+use crate::gold_alignment::GoldAlignment;
 use crate::lang::LangKey;
+use crate::line_index::LineIndex;
 use crate::link::Link;
+use crate::moses::MosesEscaping;
+use crate::rtoks_builder::RToksBuilder;
 use regex::Regex;
 use std::error::Error;
 use std::fs::File;
 use std::io::prelude::*;
-use std::io::BufReader;
+use std::io::Lines;
+use std::io::{BufReader, SeekFrom};
 
 lazy_static! {
     static ref LINK_RE: Regex = Regex::new(r"(\d+)-(\d+)").unwrap();
+    static ref GOLD_LINK_RE: Regex = Regex::new(r"^(\d+)([-?])(\d+)(?:-([SP]))?$").unwrap();
 }
 
+pub type LineIter = Lines<Box<dyn BufRead>>;
+
 pub struct Reader {
     pub config: Config,
 }
@@ -21,6 +31,18 @@ quick_error! {
         ParseLink { }
         GetValue { }
         ParseNumber { }
+        ParseGizaHeader { }
+        ParseGizaAlignment { }
+        ParseFastAlign { }
+        MissingColumn(column: usize) {
+            display("Missing column {}", column)
+        }
+        MissingField(name: String) {
+            display("Missing field {}", name)
+        }
+        InvalidField(name: String) {
+            display("Invalid field {}", name)
+        }
     }
 }
 
@@ -43,24 +65,70 @@ impl Reader {
     }
 
     pub fn parse_links(line: &str) -> Result<Vec<Link>, ParsingError> {
-        let links: Vec<_> = line
-            .split_whitespace()
-            .map(|tok| Self::parse_link(tok))
-            .collect();
-        for l in &links {
-            if l.is_err() {
-                let e = l.as_ref().err();
-                return Err(e.unwrap().clone());
+        line.split_whitespace().map(Self::parse_link).collect()
+    }
+
+    /// Parses `N-M` or `N-M-S` as sure links and `N?M` or `N-M-P` as
+    /// possible links.
+    pub fn parse_gold_links(line: &str) -> Result<GoldAlignment, ParsingError> {
+        let mut gold = GoldAlignment::default();
+        for tok in line.split_whitespace() {
+            let caps = GOLD_LINK_RE.captures(tok).ok_or(ParsingError::ParseLink)?;
+            let link = Link {
+                source: caps[1].parse().map_err(|_| ParsingError::ParseNumber)?,
+                target: caps[3].parse().map_err(|_| ParsingError::ParseNumber)?,
+            };
+            let is_possible = &caps[2] == "?" || caps.get(4).map(|flag| flag.as_str()) == Some("P");
+            if is_possible {
+                gold.possible.push(link);
+            } else {
+                gold.sure.push(link);
             }
         }
-        let links: Vec<Link> = links.into_iter().map(|l| l.unwrap()).collect();
-        Ok(links)
+        Ok(gold)
     }
 
     pub fn parse_toks(line: &str) -> Vec<String> {
         line.split_whitespace().map(|tok| tok.to_string()).collect()
     }
 
+    /// Splits Moses factored tokens such as `word|NN|lemma` into the surface
+    /// factors and, for each token, the remaining factors.
+    pub fn parse_factored_toks(line: &str, separator: &str) -> (Vec<String>, Vec<Vec<String>>) {
+        line.split_whitespace()
+            .map(|tok| Self::parse_factored_tok(tok, separator))
+            .unzip()
+    }
+
+    pub fn parse_factored_tok(tok: &str, separator: &str) -> (String, Vec<String>) {
+        let mut factors = tok.split(separator).map(|factor| factor.to_string());
+        let surface = factors.next().unwrap_or_default();
+        (surface, factors.collect())
+    }
+
+    /// Parses a token line into surface tokens and, if `separator` is
+    /// given, the remaining factors of each token; without a separator the
+    /// factors are empty.
+    pub fn parse_toks_with_factors(
+        line: &str,
+        separator: Option<&str>,
+    ) -> (Vec<String>, Vec<Vec<String>>) {
+        match separator {
+            Some(separator) => Self::parse_factored_toks(line, separator),
+            None => (Self::parse_toks(line), vec![]),
+        }
+    }
+
+    /// Parses a token line, keeping only the surface factor if `separator`
+    /// is given.
+    pub fn parse_surface_toks(line: &str, separator: Option<&str>) -> Vec<String> {
+        Self::parse_toks_with_factors(line, separator).0
+    }
+
+    pub fn surface_toks(&self, line: &str) -> Vec<String> {
+        Self::parse_surface_toks(line, self.config.factor_separator.as_deref())
+    }
+
     fn lang_key_to_lang(&self, lang_key: LangKey) -> String {
         match lang_key {
             LangKey::SOURCE => self.config.langs.source.to_string(),
@@ -68,49 +136,147 @@ impl Reader {
         }
     }
 
-    pub fn read_toks(&self, lang_key: LangKey) -> Result<Vec<Vec<String>>, Box<Error>> {
+    pub fn moses_escaping(&self, lang_key: LangKey) -> MosesEscaping {
+        self.config.moses_escaping(&self.lang_key_to_lang(lang_key))
+    }
+
+    pub fn rtoks_builder(&self, lang_key: LangKey) -> RToksBuilder {
+        RToksBuilder::from_config_for_lang(&self.config, &self.lang_key_to_lang(lang_key))
+    }
+
+    pub fn toks_path(&self, lang_key: LangKey) -> String {
         let lang = self.lang_key_to_lang(lang_key);
-        let path = format!(
+        format!(
             "{}/{}.{}",
             self.config.corpus_dir, self.config.tok_prefix, lang
-        );
-        let f = File::open(path)?;
-        let reader = BufReader::new(f);
-        let toks_list: Vec<_> = reader
-            .lines()
-            .skip(self.config.textunit_offset)
-            .take(self.config.textunit_limit)
-            .map(|line| Self::parse_toks(&line.unwrap()))
-            .collect();
-        Ok(toks_list)
+        )
     }
 
-    pub fn read_lines(&self, lang_key: LangKey) -> Result<Vec<String>, Box<Error>> {
+    pub fn lines_path(&self, lang_key: LangKey) -> String {
         let lang = self.lang_key_to_lang(lang_key);
-        let path = format!(
+        format!(
             "{}/{}.{}",
             self.config.corpus_dir, self.config.orig_prefix, lang
-        );
-        let f = File::open(path)?;
-        let reader = BufReader::new(f);
-        let lines: Vec<_> = reader
-            .lines()
-            .skip(self.config.textunit_offset)
+        )
+    }
+
+    pub fn links_path(&self) -> String {
+        self.config.alignment_file_path.to_string()
+    }
+
+    pub fn input_paths(&self) -> Vec<String> {
+        vec![
+            self.links_path(),
+            self.toks_path(LangKey::SOURCE),
+            self.toks_path(LangKey::TARGET),
+            self.lines_path(LangKey::SOURCE),
+            self.lines_path(LangKey::TARGET),
+        ]
+    }
+
+    pub fn count_lines(path: &str) -> Result<usize, Box<dyn Error>> {
+        let mut reader = open_input(path)?;
+        let mut buf = vec![];
+        let mut count = 0;
+        while reader.read_until(b'\n', &mut buf)? > 0 {
+            count += 1;
+            buf.clear();
+        }
+        Ok(count)
+    }
+
+    pub fn build_line_indexes(&self) -> Result<(), Box<dyn Error>> {
+        for path in self.input_paths() {
+            LineIndex::build(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Opens `path` and skips the first `textunit_offset` lines, seeking
+    /// directly if `path` has an up-to-date line index.
+    pub fn open_lines(&self, path: &str) -> Result<LineIter, Box<dyn Error>> {
+        if self.config.textunit_offset > 0 {
+            if let Some(index) = LineIndex::open(path)? {
+                let mut f = File::open(path)?;
+                f.seek(SeekFrom::Start(index.offset(self.config.textunit_offset)))?;
+                let reader: Box<dyn BufRead> = Box::new(BufReader::new(f));
+                return Ok(reader.lines());
+            }
+        }
+        let mut lines = open_input(path)?.lines();
+        for _ in 0..self.config.textunit_offset {
+            if lines.next().is_none() {
+                break;
+            }
+        }
+        Ok(lines)
+    }
+
+    pub fn read_toks(&self, lang_key: LangKey) -> Result<Vec<Vec<String>>, Box<dyn Error>> {
+        let mut toks_list = vec![];
+        for line in self
+            .open_lines(&self.toks_path(lang_key))?
+            .take(self.config.textunit_limit)
+        {
+            toks_list.push(self.surface_toks(&line?));
+        }
+        Ok(toks_list)
+    }
+
+    /// Reads the non-surface factors of each token, parallel to `read_toks`.
+    pub fn read_factors(&self, lang_key: LangKey) -> Result<Vec<Vec<Vec<String>>>, Box<dyn Error>> {
+        let separator = self
+            .config
+            .factor_separator
+            .as_ref()
+            .ok_or(ParsingError::MissingField(String::from("factor_separator")))?;
+        let mut factors_list = vec![];
+        for line in self
+            .open_lines(&self.toks_path(lang_key))?
             .take(self.config.textunit_limit)
-            .map(|line| line.unwrap())
-            .collect();
+        {
+            factors_list.push(Self::parse_factored_toks(&line?, separator).1);
+        }
+        Ok(factors_list)
+    }
+
+    pub fn read_lines(&self, lang_key: LangKey) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut lines = vec![];
+        for line in self
+            .open_lines(&self.lines_path(lang_key))?
+            .take(self.config.textunit_limit)
+        {
+            lines.push(line?);
+        }
         Ok(lines)
     }
 
-    pub fn read_links(&self) -> Result<Vec<Vec<Link>>, Box<Error>> {
-        let f = File::open(&self.config.alignment_file_path)?;
-        let reader = BufReader::new(f);
-        let mut links_list = vec![];
-        for line in reader
-            .lines()
+    pub fn read_links(&self) -> Result<Vec<Vec<Link>>, Box<dyn Error>> {
+        self.read_links_from(&self.links_path())
+    }
+
+    pub fn read_gold_links(&self, path: &str) -> Result<Vec<GoldAlignment>, Box<dyn Error>> {
+        let mut golds = vec![];
+        for line in self.open_lines(path)?.take(self.config.textunit_limit) {
+            golds.push(Self::parse_gold_links(&line?)?);
+        }
+        Ok(golds)
+    }
+
+    pub fn read_giza_a3(&self, path: &str) -> Result<Vec<GizaAlignment>, Box<dyn Error>> {
+        let mut alignments = vec![];
+        for alignment in GizaA3Iter::new(open_input(path)?)
             .skip(self.config.textunit_offset)
             .take(self.config.textunit_limit)
         {
+            alignments.push(alignment?);
+        }
+        Ok(alignments)
+    }
+
+    pub fn read_links_from(&self, path: &str) -> Result<Vec<Vec<Link>>, Box<dyn Error>> {
+        let mut links_list = vec![];
+        for line in self.open_lines(path)?.take(self.config.textunit_limit) {
             let line = line?;
             let links = Self::parse_links(&line)?;
             links_list.push(links);
@@ -120,6 +286,7 @@ impl Reader {
 }
 
 #[cfg(test)]
+#[allow(clippy::redundant_field_names)]
 mod tests {
     use super::*;
     use crate::config::Langs;
@@ -143,6 +310,37 @@ mod tests {
         )
     }
 
+    #[test]
+    fn parse_gold_links() {
+        let gold = Reader::parse_gold_links("0-0 1?2 2-3-P 3-4-S").unwrap();
+        assert_eq!(
+            gold.sure,
+            vec![
+                Link {
+                    source: 0,
+                    target: 0
+                },
+                Link {
+                    source: 3,
+                    target: 4
+                }
+            ]
+        );
+        assert_eq!(
+            gold.possible,
+            vec![
+                Link {
+                    source: 1,
+                    target: 2
+                },
+                Link {
+                    source: 2,
+                    target: 3
+                }
+            ]
+        );
+    }
+
     #[test]
     fn parse_simple_link() {
         assert_eq!(
@@ -170,6 +368,7 @@ mod tests {
             output_amphigram_path: String::from(""),
             textunit_limit: 100,
             textunit_offset: 0,
+            ..Config::default()
         };
         let reader = Reader { config: conf };
         let lines = reader.read_lines(LangKey::SOURCE).unwrap();
@@ -193,6 +392,7 @@ mod tests {
             output_amphigram_path: String::from(""),
             textunit_limit: 100,
             textunit_offset: 0,
+            ..Config::default()
         };
         let reader = Reader { config: conf };
         let toks = reader.read_toks(LangKey::SOURCE).unwrap();
@@ -222,6 +422,7 @@ mod tests {
             output_amphigram_path: String::from(""),
             textunit_limit: 100,
             textunit_offset: 0,
+            ..Config::default()
         };
         let reader = Reader { config: conf };
         let links = reader.read_links().unwrap();
@@ -243,4 +444,25 @@ mod tests {
         ];
         assert_eq!(links, expected);
     }
+
+    #[test]
+    fn read_factored_toks() {
+        let root = env!("CARGO_MANIFEST_DIR");
+        let langs = Langs {
+            source: String::from("en"),
+            target: String::from("th"),
+        };
+        let conf = Config {
+            corpus_dir: format!("{}/data", root),
+            tok_prefix: String::from("factored-toks"),
+            langs,
+            factor_separator: Some(String::from("|")),
+            ..Config::default()
+        };
+        let reader = Reader { config: conf };
+        let toks = reader.read_toks(LangKey::SOURCE).unwrap();
+        assert_eq!(toks[0], vec!["A", "black", "cat"]);
+        let factors = reader.read_factors(LangKey::SOURCE).unwrap();
+        assert_eq!(factors[0][2], vec!["NN", "cat"]);
+    }
 }
@@ -1,61 +1,286 @@
+use crate::config::Config;
+use crate::moses::{MosesEscaping, MosesStrMod};
+use crate::offset_unit::{OffsetConverter, OffsetError, OffsetUnit};
 use crate::rtok::RTok;
 use crate::utf16::{substring, utf16_len};
+use caseless::default_case_fold_str;
+use serde::{Deserialize, Serialize};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
-trait StrMod {
+/// Maps a token and a span of the original text into a common form before
+/// they are compared. The span has the UTF-16 length of `mod_tok(tok)`,
+/// unless `mod_orig` may change lengths, in which case spans of any length
+/// are tried.
+///
+/// A builder tries its `StrMod`s one at a time, they are alternatives, not
+/// a pipeline: the output of one is never fed to the next.
+pub trait StrMod: Send + Sync {
     fn mod_orig(&self, s: &str) -> String {
         s.to_string()
     }
     fn mod_tok(&self, s: &str) -> String {
         s.to_string()
     }
+    /// Whether `mod_orig` keeps the UTF-16 length of its input.
+    fn keeps_orig_len(&self) -> bool {
+        true
+    }
 }
 
-struct IdentityStrMod;
+/// The default `StrMod`: undoes all Moses escapes, as `MosesStrMod` with
+/// the default `MosesEscaping`.
+pub struct IdentityStrMod;
 
 impl StrMod for IdentityStrMod {
     fn mod_tok(&self, s: &str) -> String {
-        s.replace("&apos;", "'")
-            .replace("\u{F112}", " ")
-            .replace("&quot;", "\"")
-            .replace("&amp;", "&")
-            .replace("&#91;", "[")
-            .replace("&#93;", "]")
-            .to_string()
+        MosesEscaping::default().unescape(s)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub from: String,
+    pub to: String,
+}
+
+/// Replaces strings in tokens (`tok`) and in the original text (`orig`),
+/// applying the rules of each side in order, e.g. `-LRB-` -> `(` in tokens
+/// or `๑` -> `1` or `…` -> `...` in the original text.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RuleStrMod {
+    #[serde(default)]
+    pub tok: Vec<Rule>,
+    #[serde(default)]
+    pub orig: Vec<Rule>,
+}
+
+impl RuleStrMod {
+    fn apply(rules: &[Rule], s: &str) -> String {
+        rules
+            .iter()
+            .fold(s.to_string(), |s, rule| s.replace(&rule.from, &rule.to))
+    }
+}
+
+impl StrMod for RuleStrMod {
+    fn mod_orig(&self, s: &str) -> String {
+        Self::apply(&self.orig, s)
+    }
+    fn mod_tok(&self, s: &str) -> String {
+        Self::apply(&self.tok, s)
+    }
+    fn keeps_orig_len(&self) -> bool {
+        self.orig
+            .iter()
+            .all(|rule| utf16_len(&rule.from) == utf16_len(&rule.to))
     }
 }
 
 quick_error! {
     #[derive(Debug)]
     pub enum CharAlignError {
-        CannotMatchSomeToks(text: String, toks: Vec<String>, i: usize, s: usize, orig_len: usize) {
+        /// `search_s` is where the search for token `i` started, the end of
+        /// the last matched token.
+        CannotMatchSomeToks(text: String, toks: Vec<String>, i: usize, s: usize, orig_len: usize, search_s: usize) {
             display("Cannon match: text={} toks={:?} i={} s={} orig_len={}", &text, &toks, i, s, orig_len)
         }
+        InvalidOffset(err: OffsetError) {
+            from()
+            display("Cannot convert aligned offsets: {}", err)
+        }
+    }
+}
+
+impl CharAlignError {
+    pub fn context(&self, width: usize) -> String {
+        match self {
+            CharAlignError::CannotMatchSomeToks(text, toks, i, _, _, search_s) => {
+                let units: Vec<_> = text.encode_utf16().collect();
+                let s = (*search_s).min(units.len());
+                let before = String::from_utf16_lossy(&units[s.saturating_sub(width)..s]);
+                let after = String::from_utf16_lossy(&units[s..(s + width).min(units.len())]);
+                format!("{}|{} <- {:?}", before, after, toks[*i])
+            }
+            CharAlignError::InvalidOffset(_) => String::new(),
+        }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum AlignMode {
+    /// Places each token at the first position it matches.
+    #[default]
+    Greedy,
+    /// Places all tokens at once by minimal edit distance, see
+    /// `RToksBuilder::match_text_toks`.
+    Dp,
+}
+
+/// Unicode normalization applied to both sides before comparing.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Normalization {
+    #[default]
+    None,
+    Nfc,
+    Nfkc,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RTokMatch {
+    pub rtok: RTok,
+    /// Matched characters relative to the longer of the token and its span,
+    /// 1.0 for an exact match.
+    pub confidence: f64,
+}
+
 pub struct RToksBuilder {
-    str_mods: Vec<Box<StrMod>>,
+    str_mods: Vec<Box<dyn StrMod>>,
+    align_mode: AlignMode,
+    case_folding: bool,
+    normalization: Normalization,
+    offset_unit: OffsetUnit,
+}
+
+impl Default for RToksBuilder {
+    fn default() -> RToksBuilder {
+        RToksBuilder::new()
+    }
 }
 
 impl RToksBuilder {
     pub fn new() -> RToksBuilder {
+        RToksBuilder::with_str_mods(vec![Box::new(IdentityStrMod {})])
+    }
+
+    /// Tokens are matched by trying each of `str_mods` in order, on the
+    /// token and the original text as they are.
+    pub fn with_str_mods(str_mods: Vec<Box<dyn StrMod>>) -> RToksBuilder {
         RToksBuilder {
-            str_mods: vec![Box::new(IdentityStrMod {}), Box::new(IdentityStrMod {})],
+            str_mods,
+            align_mode: AlignMode::Greedy,
+            case_folding: false,
+            normalization: Normalization::None,
+            offset_unit: OffsetUnit::Utf16,
         }
     }
 
+    /// The unit of the offsets in the returned `RTok`s. Offsets in
+    /// `CharAlignError` stay in UTF-16 code units.
+    pub fn offset_unit(mut self, offset_unit: OffsetUnit) -> RToksBuilder {
+        self.offset_unit = offset_unit;
+        self
+    }
+
+    /// The unit set with `offset_unit`.
+    pub fn output_offset_unit(&self) -> OffsetUnit {
+        self.offset_unit
+    }
+
+    fn to_offset_unit(&self, orig: &str, rtoks: Vec<RTok>) -> Result<Vec<RTok>, CharAlignError> {
+        if self.offset_unit == OffsetUnit::Utf16 {
+            return Ok(rtoks);
+        }
+        let converter = OffsetConverter::new(orig);
+        Ok(converter.convert_rtoks(&rtoks, OffsetUnit::Utf16, self.offset_unit)?)
+    }
+
+    /// Compares with full Unicode case folding instead of ignoring ASCII
+    /// case only.
+    pub fn case_folding(mut self, case_folding: bool) -> RToksBuilder {
+        self.case_folding = case_folding;
+        self
+    }
+
+    pub fn normalization(mut self, normalization: Normalization) -> RToksBuilder {
+        self.normalization = normalization;
+        self
+    }
+
+    fn is_plain(&self) -> bool {
+        !self.case_folding && self.normalization == Normalization::None
+    }
+
+    fn normalize(&self, s: &str) -> String {
+        match self.normalization {
+            Normalization::None => s.to_string(),
+            Normalization::Nfc => s.nfc().collect(),
+            Normalization::Nfkc => s.nfkc().collect(),
+        }
+    }
+
+    fn key(&self, s: &str) -> String {
+        let s = self.normalize(s);
+        if self.case_folding {
+            self.normalize(&default_case_fold_str(&s))
+        } else {
+            s
+        }
+    }
+
+    /// Characters of `s` case folded and decomposed, for the DP aligner.
+    fn decomposed(&self, s: &str) -> Vec<char> {
+        let s = if self.case_folding {
+            default_case_fold_str(s)
+        } else {
+            s.to_string()
+        };
+        match self.normalization {
+            Normalization::None => s.chars().collect(),
+            Normalization::Nfc => s.nfd().collect(),
+            Normalization::Nfkc => s.nfkd().collect(),
+        }
+    }
+
+    pub fn align_mode(mut self, align_mode: AlignMode) -> RToksBuilder {
+        self.align_mode = align_mode;
+        self
+    }
+
+    /// The default normalizer followed by the `normalizers` of `config`.
+    pub fn from_config(config: &Config) -> RToksBuilder {
+        RToksBuilder::new().add_normalizers(config)
+    }
+
+    /// Like `from_config`, but with Moses unescaping as configured for
+    /// `lang` in place of `IdentityStrMod`, so that `entities = false`
+    /// leaves entities alone.
+    pub fn from_config_for_lang(config: &Config, lang: &str) -> RToksBuilder {
+        RToksBuilder::with_str_mods(vec![Box::new(MosesStrMod {
+            escaping: config.moses_escaping(lang),
+        })])
+        .add_normalizers(config)
+    }
+
+    fn add_normalizers(self, config: &Config) -> RToksBuilder {
+        config
+            .normalizers
+            .iter()
+            .cloned()
+            .fold(self, |builder, rules| builder.add_str_mod(Box::new(rules)))
+    }
+
+    pub fn add_str_mod(mut self, str_mod: Box<dyn StrMod>) -> RToksBuilder {
+        self.str_mods.push(str_mod);
+        self
+    }
+
     fn match_tok_internal<T: ?Sized + StrMod>(
+        &self,
         s: usize,
         orig: &str,
         tok: &str,
-        str_mod: &Box<T>,
+        str_mod: &T,
     ) -> Option<String> {
         let tok = str_mod.mod_tok(tok);
+        if !self.is_plain() || !str_mod.keeps_orig_len() {
+            return self.match_tok_key(s, orig, &tok, str_mod);
+        }
         let e = s + utf16_len(&tok);
         if e > utf16_len(orig) {
             return None;
         }
-        let prefix = substring(orig, s, e).unwrap();
+        let prefix = substring(orig, s, e).ok()?;
         let mod_prefix = str_mod.mod_orig(&prefix);
         if mod_prefix.eq_ignore_ascii_case(&tok) {
             Some(prefix)
@@ -64,9 +289,41 @@ impl RToksBuilder {
         }
     }
 
+    /// Finds the shortest span from `s` whose key equals the key of `tok`.
+    /// The span may differ in length from `tok` but never ends before a
+    /// combining mark.
+    fn match_tok_key<T: ?Sized + StrMod>(
+        &self,
+        s: usize,
+        orig: &str,
+        tok: &str,
+        str_mod: &T,
+    ) -> Option<String> {
+        let tok_key = self.key(tok);
+        if tok_key.is_empty() {
+            return Some(String::new());
+        }
+        let tok_key_len = tok_key.chars().count();
+        let rest = substring(orig, s, utf16_len(orig)).ok()?;
+        for (i, c) in rest.char_indices() {
+            let e = i + c.len_utf8();
+            if rest[e..].chars().next().is_some_and(is_combining_mark) {
+                continue;
+            }
+            let key = self.key(&str_mod.mod_orig(&rest[..e]));
+            if key.eq_ignore_ascii_case(&tok_key) {
+                return Some(rest[..e].to_string());
+            }
+            if key.chars().count() > tok_key_len {
+                break;
+            }
+        }
+        None
+    }
+
     fn match_tok(&self, orig: &str, tok: &str, s: usize) -> Option<String> {
         for mod_str in &self.str_mods {
-            let prefix = Self::match_tok_internal(s, orig, tok, &mod_str);
+            let prefix = self.match_tok_internal(s, orig, tok, mod_str.as_ref());
             if prefix.is_some() {
                 return prefix;
             }
@@ -74,10 +331,181 @@ impl RToksBuilder {
         None
     }
 
+    /// Aligns with the configured `AlignMode`; `AlignMode::Dp` only fails if
+    /// the offsets cannot be converted to the configured `OffsetUnit`.
     pub fn align_text_toks(
         &self,
         orig: &str,
         toks: &[String],
+    ) -> Result<Vec<RTok>, CharAlignError> {
+        match self.align_mode {
+            AlignMode::Greedy => {
+                let rtoks = self.align_text_toks_greedy(orig, toks)?;
+                self.to_offset_unit(orig, rtoks)
+            }
+            AlignMode::Dp => Ok(self
+                .match_text_toks(orig, toks)?
+                .into_iter()
+                .map(|rtok_match| rtok_match.rtok)
+                .collect()),
+        }
+    }
+
+    fn tok_form(&self, tok: &str) -> String {
+        self.str_mods
+            .iter()
+            .map(|str_mod| str_mod.mod_tok(tok))
+            .find(|form| form != tok)
+            .unwrap_or_else(|| tok.to_string())
+    }
+
+    /// The decomposed units of `c`, each with the forms it may match. A
+    /// single unit also matches its single-character forms under each
+    /// `mod_orig`.
+    fn orig_units(&self, c: char) -> Vec<Vec<char>> {
+        let orig = c.to_string();
+        let units = self.decomposed(&orig);
+        if units.len() != 1 {
+            return units.into_iter().map(|unit| vec![unit]).collect();
+        }
+        let mut forms = units;
+        for str_mod in &self.str_mods {
+            let modded = self.decomposed(&str_mod.mod_orig(&orig));
+            if modded.len() == 1 {
+                forms.push(modded[0]);
+            }
+        }
+        vec![forms]
+    }
+
+    /// Finds the placement of all tokens in `orig` with the fewest edits.
+    /// Skipping whitespace in `orig` is free; skipping other characters,
+    /// dropping token characters and substitutions cost one each. Tokens are
+    /// normalized with the first `StrMod` that changes them, and `mod_orig`
+    /// is applied one character at a time. Case folding and normalization
+    /// compare decomposed characters, while spans cover whole characters of
+    /// `orig`.
+    pub fn match_text_toks(
+        &self,
+        orig: &str,
+        toks: &[String],
+    ) -> Result<Vec<RTokMatch>, CharAlignError> {
+        let orig_chars: Vec<(usize, char)> = orig.char_indices().collect();
+        // (index in orig_chars, forms) of each decomposed unit of orig
+        let orig_units: Vec<(usize, Vec<char>)> = orig_chars
+            .iter()
+            .enumerate()
+            .flat_map(|(k, (_, c))| self.orig_units(*c).into_iter().map(move |forms| (k, forms)))
+            .collect();
+        let mut tok_chars = vec![];
+        let mut tok_ends = vec![];
+        for tok in toks {
+            tok_chars.extend(self.decomposed(&self.tok_form(tok)));
+            tok_ends.push(tok_chars.len());
+        }
+        let char_matches = |i: usize, j: usize| {
+            orig_units[i]
+                .1
+                .iter()
+                .any(|form| form.eq_ignore_ascii_case(&tok_chars[j]))
+        };
+        let n = orig_units.len();
+        let m = tok_chars.len();
+        let skip_cost = |i: usize| u32::from(!orig_units[i].1[0].is_whitespace());
+        // cost[i][j]: aligning orig_units[..i] with tok_chars[..j]
+        let mut cost = vec![vec![0u32; m + 1]; n + 1];
+        cost[0] = (0..=m as u32).collect();
+        for i in 1..=n {
+            cost[i][0] = cost[i - 1][0] + skip_cost(i - 1);
+            for j in 1..=m {
+                let sub = u32::from(!char_matches(i - 1, j - 1));
+                cost[i][j] = (cost[i - 1][j - 1] + sub)
+                    .min(cost[i - 1][j] + skip_cost(i - 1))
+                    .min(cost[i][j - 1] + 1);
+            }
+        }
+        // orig_pos[j]: the unit aligned with tok_chars[j] and whether it
+        // matched; gap[j]: the unit before which tok_chars[j] would go if
+        // dropped
+        let mut orig_pos: Vec<Option<(usize, bool)>> = vec![None; m];
+        let mut gap = vec![0; m];
+        let (mut i, mut j) = (n, m);
+        while j > 0 {
+            if i > 0 {
+                let matched = char_matches(i - 1, j - 1);
+                if cost[i][j] == cost[i - 1][j - 1] + u32::from(!matched) {
+                    orig_pos[j - 1] = Some((i - 1, matched));
+                    i -= 1;
+                    j -= 1;
+                    continue;
+                }
+                if cost[i][j] == cost[i - 1][j] + skip_cost(i - 1) {
+                    i -= 1;
+                    continue;
+                }
+            }
+            gap[j - 1] = i;
+            j -= 1;
+        }
+        let utf16_offsets: Vec<usize> = orig_chars
+            .iter()
+            .scan(0, |offset, (_, c)| {
+                let s = *offset;
+                *offset += c.len_utf16();
+                Some(s)
+            })
+            .chain(std::iter::once(utf16_len(orig)))
+            .collect();
+        let byte_offset = |k: usize| orig_chars.get(k).map_or(orig.len(), |(b, _)| *b);
+        let char_index = |i: usize| orig_units.get(i).map_or(orig_chars.len(), |unit| unit.0);
+        let mut matches = vec![];
+        let mut tok_s = 0;
+        let mut prev_e = 0;
+        for &tok_e in &tok_ends {
+            let aligned: Vec<(usize, bool)> =
+                orig_pos[tok_s..tok_e].iter().flatten().cloned().collect();
+            let (s, e, span_len) = match (aligned.first(), aligned.last()) {
+                (Some(&(s, _)), Some(&(e, _))) => (char_index(s), char_index(e) + 1, e + 1 - s),
+                _ => {
+                    let at = if tok_s < tok_e {
+                        char_index(gap[tok_s])
+                    } else {
+                        prev_e
+                    };
+                    (at, at, 0)
+                }
+            };
+            let matched = aligned.iter().filter(|(_, matched)| *matched).count();
+            let len = (tok_e - tok_s).max(span_len);
+            let confidence = if len == 0 {
+                1.0
+            } else {
+                matched as f64 / len as f64
+            };
+            matches.push(RTokMatch {
+                rtok: RTok {
+                    s: utf16_offsets[s],
+                    e: utf16_offsets[e],
+                    text: orig[byte_offset(s)..byte_offset(e)].to_string(),
+                },
+                confidence,
+            });
+            tok_s = tok_e;
+            prev_e = e;
+        }
+        if self.offset_unit != OffsetUnit::Utf16 {
+            let rtoks = matches.iter().map(|m| m.rtok.clone()).collect();
+            for (m, rtok) in matches.iter_mut().zip(self.to_offset_unit(orig, rtoks)?) {
+                m.rtok = rtok;
+            }
+        }
+        Ok(matches)
+    }
+
+    fn align_text_toks_greedy(
+        &self,
+        orig: &str,
+        toks: &[String],
     ) -> Result<Vec<RTok>, CharAlignError> {
         let mut s = 0;
         let mut i = 0;
@@ -88,22 +516,20 @@ impl RToksBuilder {
                 return Ok(aligned_toks);
             }
             if s >= orig_len {
+                let search_s = aligned_toks.last().map_or(0, |rtok: &RTok| rtok.e);
                 return Err(CharAlignError::CannotMatchSomeToks(
                     String::from(orig),
                     toks.to_vec(),
                     i,
                     s,
                     orig_len,
+                    search_s,
                 ));
             }
             let tok = &toks[i];
             if let Some(prefix) = self.match_tok(orig, tok, s) {
                 let e = s + utf16_len(&prefix[..]);
-                let aligned_tok = RTok {
-                    s: s,
-                    e: e,
-                    text: prefix,
-                };
+                let aligned_tok = RTok { s, e, text: prefix };
                 s = e;
                 i += 1;
                 aligned_toks.push(aligned_tok);
@@ -190,4 +616,128 @@ mod tests {
         assert_eq!(rtoks[0], rtok0);
     }
 
+    #[test]
+    fn align_with_rule_str_mod() {
+        let rules = RuleStrMod {
+            tok: vec![Rule {
+                from: String::from("-LRB-"),
+                to: String::from("("),
+            }],
+            orig: vec![Rule {
+                from: String::from("๑"),
+                to: String::from("1"),
+            }],
+        };
+        let toks = vec![String::from("-LRB-"), String::from("1")];
+        assert!(RToksBuilder::new().align_text_toks("(๑", &toks).is_err());
+        let builder = RToksBuilder::new().add_str_mod(Box::new(rules));
+        let rtoks = builder.align_text_toks("(๑", &toks).unwrap();
+        assert_eq!(rtoks[0].text, "(");
+        assert_eq!(rtoks[1].text, "๑");
+    }
+
+    #[test]
+    fn align_with_length_changing_rule() {
+        let rules = RuleStrMod {
+            tok: vec![],
+            orig: vec![Rule {
+                from: String::from("…"),
+                to: String::from("..."),
+            }],
+        };
+        let toks = vec![
+            String::from("Wait"),
+            String::from("..."),
+            String::from("no"),
+        ];
+        let builder = RToksBuilder::new().add_str_mod(Box::new(rules));
+        let rtoks = builder.align_text_toks("Wait… no", &toks).unwrap();
+        assert_eq!(rtoks[1].text, "…");
+        assert_eq!((rtoks[1].s, rtoks[1].e), (4, 5));
+        assert_eq!((rtoks[2].s, rtoks[2].e), (6, 8));
+    }
+
+    #[test]
+    fn dp_align_missing_char() {
+        let text = "Fish a cat";
+        let toks = vec![String::from("Fsh"), String::from("a"), String::from("cat")];
+        assert!(RToksBuilder::new().align_text_toks(text, &toks).is_err());
+        let builder = RToksBuilder::new().align_mode(AlignMode::Dp);
+        let matches = builder.match_text_toks(text, &toks).unwrap();
+        assert_eq!(matches[0].rtok.text, "Fish");
+        assert_eq!(matches[0].confidence, 0.75);
+        assert_eq!(matches[1].rtok.s, 5);
+        assert_eq!(matches[1].confidence, 1.0);
+        assert_eq!(matches[2].rtok.s, 7);
+    }
+
+    #[test]
+    fn dp_align_with_edits() {
+        let builder = RToksBuilder::new().align_mode(AlignMode::Dp);
+        let toks = vec![String::from("colour"), String::from("ดำ")];
+        let matches = builder.match_text_toks("the color ดำ", &toks).unwrap();
+        assert_eq!(matches[0].rtok.text, "color");
+        assert!(matches[0].confidence < 1.0 && matches[0].confidence > 0.5);
+        assert_eq!(matches[1].rtok.s, 10);
+        assert_eq!(matches[1].confidence, 1.0);
+        let rtoks = builder.align_text_toks("AB C", &[String::from("AB"), String::from("C")]);
+        assert_eq!(rtoks.unwrap()[1].s, 3);
+    }
+
+    #[test]
+    fn unicode_case_folding_and_normalization() {
+        let toks = vec![
+            String::from("äpfel"),
+            String::from("москва"),
+            String::from("é"),
+        ];
+        // "Ä" precomposed, "e" followed by a combining acute accent
+        let text = "Äpfel МОСКВА e\u{301}!";
+        assert!(RToksBuilder::new().align_text_toks(text, &toks).is_err());
+        let builder = RToksBuilder::new()
+            .case_folding(true)
+            .normalization(Normalization::Nfc);
+        let rtoks = builder.align_text_toks(text, &toks).unwrap();
+        assert_eq!(rtoks[1].text, "МОСКВА");
+        assert_eq!((rtoks[2].s, rtoks[2].e), (13, 15));
+        let matches = builder
+            .align_mode(AlignMode::Dp)
+            .match_text_toks(text, &toks)
+            .unwrap();
+        assert_eq!(matches[0].rtok.text, "Äpfel");
+        assert_eq!((matches[2].rtok.s, matches[2].rtok.e), (13, 15));
+        assert_eq!(matches[2].confidence, 1.0);
+        let builder = RToksBuilder::new().normalization(Normalization::Nfkc);
+        let rtoks = builder
+            .align_text_toks("ﬁne", &[String::from("fine")])
+            .unwrap();
+        assert_eq!((rtoks[0].s, rtoks[0].e), (0, 3));
+    }
+
+    #[test]
+    fn align_in_offset_unit() {
+        let toks = vec![String::from("น้ำ"), String::from("cat")];
+        let builder = RToksBuilder::new().offset_unit(OffsetUnit::Grapheme);
+        let rtoks = builder.align_text_toks("น้ำ cat", &toks).unwrap();
+        assert_eq!((rtoks[1].s, rtoks[1].e), (2, 5));
+        let builder = builder
+            .align_mode(AlignMode::Dp)
+            .offset_unit(OffsetUnit::Utf8);
+        let matches = builder.match_text_toks("น้ำ cat", &toks).unwrap();
+        assert_eq!((matches[1].rtok.s, matches[1].rtok.e), (10, 13));
+    }
+
+    #[test]
+    fn cannot_match_context() {
+        let builder = RToksBuilder::new();
+        let toks = vec![String::from("AB"), String::from("X"), String::from("C")];
+        let err = builder.align_text_toks("AB C", &toks).unwrap_err();
+        assert_eq!(err.context(5), "AB| C <- \"X\"");
+        match err {
+            CharAlignError::CannotMatchSomeToks(_, _, i, s, orig_len, search_s) => {
+                assert_eq!((i, s, orig_len, search_s), (1, 4, 4, 2));
+            }
+            _ => panic!("unexpected error {}", err),
+        }
+    }
 }
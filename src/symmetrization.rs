@@ -0,0 +1,231 @@
+use crate::link::Link;
+use crate::reader::Reader;
+use std::collections::{BTreeSet, HashSet};
+use std::error::Error;
+
+quick_error! {
+    #[derive(Debug)]
+    pub enum SymmetrizationError {
+        LineCountMismatch(s2t_path: String, s2t_len: usize, t2s_path: String, t2s_len: usize) {
+            display("Line counts differ: {}={} {}={}", s2t_path, s2t_len, t2s_path, t2s_len)
+        }
+    }
+}
+
+const NEIGHBORS: [(i64, i64); 4] = [(-1, 0), (0, -1), (1, 0), (0, 1)];
+const DIAG_NEIGHBORS: [(i64, i64); 8] = [
+    (-1, 0),
+    (0, -1),
+    (1, 0),
+    (0, 1),
+    (-1, -1),
+    (-1, 1),
+    (1, -1),
+    (1, 1),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Heuristic {
+    Intersection,
+    Union,
+    Grow,
+    GrowDiag,
+    GrowDiagFinal,
+    GrowDiagFinalAnd,
+}
+
+struct Alignment {
+    links: BTreeSet<Link>,
+    source_aligned: HashSet<usize>,
+    target_aligned: HashSet<usize>,
+}
+
+impl Alignment {
+    fn new(links: BTreeSet<Link>) -> Alignment {
+        let source_aligned = links.iter().map(|link| link.source).collect();
+        let target_aligned = links.iter().map(|link| link.target).collect();
+        Alignment {
+            links,
+            source_aligned,
+            target_aligned,
+        }
+    }
+
+    fn add(&mut self, link: Link) {
+        self.source_aligned.insert(link.source);
+        self.target_aligned.insert(link.target);
+        self.links.insert(link);
+    }
+
+    fn is_source_aligned(&self, link: &Link) -> bool {
+        self.source_aligned.contains(&link.source)
+    }
+
+    fn is_target_aligned(&self, link: &Link) -> bool {
+        self.target_aligned.contains(&link.target)
+    }
+
+    fn grow(&mut self, union: &BTreeSet<Link>, neighbors: &[(i64, i64)]) {
+        loop {
+            let mut added = false;
+            let current: Vec<Link> = self.links.iter().copied().collect();
+            for link in current {
+                for (ds, dt) in neighbors {
+                    let source = link.source as i64 + ds;
+                    let target = link.target as i64 + dt;
+                    if source < 0 || target < 0 {
+                        continue;
+                    }
+                    let neighbor = Link {
+                        source: source as usize,
+                        target: target as usize,
+                    };
+                    if self.links.contains(&neighbor) || !union.contains(&neighbor) {
+                        continue;
+                    }
+                    if !self.is_source_aligned(&neighbor) || !self.is_target_aligned(&neighbor) {
+                        self.add(neighbor);
+                        added = true;
+                    }
+                }
+            }
+            if !added {
+                break;
+            }
+        }
+    }
+
+    fn finalize(&mut self, directional: &BTreeSet<Link>, and: bool) {
+        for link in directional {
+            if self.links.contains(link) {
+                continue;
+            }
+            let source_unaligned = !self.is_source_aligned(link);
+            let target_unaligned = !self.is_target_aligned(link);
+            let accept = if and {
+                source_unaligned && target_unaligned
+            } else {
+                source_unaligned || target_unaligned
+            };
+            if accept {
+                self.add(*link);
+            }
+        }
+    }
+}
+
+/// Both inputs are in source-target orientation, i.e. `target_to_source`
+/// must already have its indices swapped back, as fast_align `-r` does.
+/// The result is sorted by source then target index.
+pub fn symmetrize(
+    source_to_target: &[Link],
+    target_to_source: &[Link],
+    heuristic: Heuristic,
+) -> Vec<Link> {
+    let s2t: BTreeSet<Link> = source_to_target.iter().copied().collect();
+    let t2s: BTreeSet<Link> = target_to_source.iter().copied().collect();
+    let union: BTreeSet<Link> = s2t.union(&t2s).copied().collect();
+    let intersection: BTreeSet<Link> = s2t.intersection(&t2s).copied().collect();
+    let mut alignment = Alignment::new(intersection);
+    match heuristic {
+        Heuristic::Intersection => {}
+        Heuristic::Union => return union.into_iter().collect(),
+        Heuristic::Grow => alignment.grow(&union, &NEIGHBORS),
+        Heuristic::GrowDiag => alignment.grow(&union, &DIAG_NEIGHBORS),
+        Heuristic::GrowDiagFinal | Heuristic::GrowDiagFinalAnd => {
+            let and = heuristic == Heuristic::GrowDiagFinalAnd;
+            alignment.grow(&union, &DIAG_NEIGHBORS);
+            alignment.finalize(&s2t, and);
+            alignment.finalize(&t2s, and);
+        }
+    }
+    alignment.links.into_iter().collect()
+}
+
+pub fn symmetrize_files(
+    reader: &Reader,
+    source_to_target_path: &str,
+    target_to_source_path: &str,
+    heuristic: Heuristic,
+) -> Result<Vec<Vec<Link>>, Box<dyn Error>> {
+    let s2t_list = reader.read_links_from(source_to_target_path)?;
+    let t2s_list = reader.read_links_from(target_to_source_path)?;
+    if s2t_list.len() != t2s_list.len() {
+        return Err(From::from(SymmetrizationError::LineCountMismatch(
+            source_to_target_path.to_string(),
+            s2t_list.len(),
+            target_to_source_path.to_string(),
+            t2s_list.len(),
+        )));
+    }
+    Ok(s2t_list
+        .iter()
+        .zip(t2s_list.iter())
+        .map(|(s2t, t2s)| symmetrize(s2t, t2s, heuristic))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::writer::format_links;
+
+    fn symmetrize_str(s2t: &str, t2s: &str, heuristic: Heuristic) -> String {
+        let s2t = Reader::parse_links(s2t).unwrap();
+        let t2s = Reader::parse_links(t2s).unwrap();
+        format_links(&symmetrize(&s2t, &t2s, heuristic))
+    }
+
+    #[test]
+    fn intersection_and_union() {
+        assert_eq!(
+            symmetrize_str("0-0 1-1", "0-0 1-2", Heuristic::Intersection),
+            "0-0"
+        );
+        assert_eq!(
+            symmetrize_str("0-0 1-1", "0-0 1-2", Heuristic::Union),
+            "0-0 1-1 1-2"
+        );
+    }
+
+    #[test]
+    fn grow_diag_adds_diagonal_neighbors() {
+        assert_eq!(symmetrize_str("0-0 1-1", "0-0", Heuristic::Grow), "0-0");
+        assert_eq!(
+            symmetrize_str("0-0 1-1", "0-0", Heuristic::GrowDiag),
+            "0-0 1-1"
+        );
+    }
+
+    #[test]
+    fn final_and_requires_both_unaligned() {
+        assert_eq!(
+            symmetrize_str("0-0 0-2 3-3", "0-0", Heuristic::GrowDiagFinal),
+            "0-0 0-2 3-3"
+        );
+        assert_eq!(
+            symmetrize_str("0-0 0-2 3-3", "0-0", Heuristic::GrowDiagFinalAnd),
+            "0-0 3-3"
+        );
+    }
+
+    #[test]
+    fn reject_differing_line_counts() {
+        let root = env!("CARGO_MANIFEST_DIR");
+        let reader = Reader {
+            config: Config::default(),
+        };
+        let err = symmetrize_files(
+            &reader,
+            &format!("{}/data/multi-links", root),
+            &format!("{}/data/shifted-links", root),
+            Heuristic::Union,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<SymmetrizationError>(),
+            Some(SymmetrizationError::LineCountMismatch(_, 3, _, 2))
+        ));
+    }
+}
@@ -1,22 +1,67 @@
 use crate::bi_rtoks::BiRToks;
 use crate::bi_text::BiText;
+use crate::compression::{create_output, open_input};
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::fast_align::split_fast_align;
 use crate::lang::LangKey;
 use crate::link::Link;
-use crate::reader::Reader;
+use crate::offset_unit::{convert_rtoks, OffsetError, OffsetUnit};
+use crate::reader::{LineIter, Reader};
+use crate::rtok::RTok;
 use crate::rtoks_builder::RToksBuilder;
+use serde::{Deserialize, Serialize};
 use std::error::Error;
+use std::io::prelude::*;
 
 quick_error! {
     #[derive(Debug)]
     pub enum TextunitLoadingError {
-        CannotLoadToks(lang: LangKey, err: Box<Error>) { }
-        CannotLoadLines(lang: LangKey, err: Box<Error>) { }
-        CannotLoadLinks(err: Box<Error>) { }
-        CannotAlignToks(lang: LangKey, line_no: usize, err: Box<Error>) { }
+        CannotLoadToks(lang: LangKey, err: Box<dyn Error>) { }
+        CannotLoadLines(lang: LangKey, err: Box<dyn Error>) { }
+        CannotLoadLinks(err: Box<dyn Error>) { }
+        CannotAlignToks(lang: LangKey, line_no: usize, err: Box<dyn Error>) { }
+        CannotReadFile(path: String, err: Box<dyn Error>) {
+            display("Cannot read {}: {}", path, err)
+        }
+        LineCountMismatch(counts: Vec<(String, usize)>) {
+            display("Line counts differ: {}", counts
+                .iter()
+                .map(|(path, count)| format!("{}={}", path, count))
+                .collect::<Vec<_>>()
+                .join(" "))
+        }
+        LinesDisagree(line_no: usize, path: String, reason: String) {
+            display("Files disagree at line {} of {}: {}", line_no, path, reason)
+        }
+        LinkOutOfRange(line_no: usize, links: Vec<Link>) {
+            display("Links out of range at line {}: {:?}", line_no, links)
+        }
+        CannotParseRecord(line_no: usize, err: Box<dyn Error>) {
+            display("Cannot parse record at line {}: {}", line_no, err)
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineCheckMode {
+    Counts,
+    FirstDisagreement,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LinkPolicy {
+    Keep,
+    Drop,
+    Fail,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidLinks {
+    pub line_no: usize,
+    pub links: Vec<Link>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 pub struct Textunit {
     pub bi_text: BiText,
     pub bi_rtoks: BiRToks,
@@ -24,71 +69,556 @@ pub struct Textunit {
 }
 
 impl Textunit {
+    pub fn align(
+        rtoks_builder: &RToksBuilder,
+        line_no: usize,
+        bi_text: BiText,
+        source_toks: &[String],
+        target_toks: &[String],
+        links: Vec<Link>,
+    ) -> Result<Textunit, TextunitLoadingError> {
+        Self::align_with_builders(
+            rtoks_builder,
+            rtoks_builder,
+            line_no,
+            bi_text,
+            source_toks,
+            target_toks,
+            links,
+        )
+    }
+
+    /// The target is aligned in the offset unit of `source_rtoks_builder`
+    /// if the builders differ.
+    pub fn align_with_builders(
+        source_rtoks_builder: &RToksBuilder,
+        target_rtoks_builder: &RToksBuilder,
+        line_no: usize,
+        bi_text: BiText,
+        source_toks: &[String],
+        target_toks: &[String],
+        links: Vec<Link>,
+    ) -> Result<Textunit, TextunitLoadingError> {
+        let source_rtoks = source_rtoks_builder
+            .align_text_toks(&bi_text.source, source_toks)
+            .map_err(|err| {
+                TextunitLoadingError::CannotAlignToks(LangKey::SOURCE, line_no, Box::new(err))
+            })?;
+        let to_target_err =
+            |err| TextunitLoadingError::CannotAlignToks(LangKey::TARGET, line_no, err);
+        let target_rtoks = target_rtoks_builder
+            .align_text_toks(&bi_text.target, target_toks)
+            .map_err(|err| to_target_err(Box::new(err)))?;
+        let offset_unit = source_rtoks_builder.output_offset_unit();
+        let target_offset_unit = target_rtoks_builder.output_offset_unit();
+        let target_rtoks = if target_offset_unit == offset_unit {
+            target_rtoks
+        } else {
+            convert_rtoks(
+                &bi_text.target,
+                &target_rtoks,
+                target_offset_unit,
+                offset_unit,
+            )
+            .map_err(|err| to_target_err(Box::new(err)))?
+        };
+        let bi_rtoks = BiRToks {
+            source: source_rtoks,
+            target: target_rtoks,
+            source_factors: vec![],
+            target_factors: vec![],
+            offset_unit,
+        };
+        Ok(Textunit {
+            bi_text,
+            bi_rtoks,
+            links,
+        })
+    }
+
+    /// The tokens of one side with offsets converted to `unit`.
+    pub fn rtoks_in(&self, lang_key: LangKey, unit: OffsetUnit) -> Result<Vec<RTok>, OffsetError> {
+        let (text, rtoks) = match lang_key {
+            LangKey::SOURCE => (&self.bi_text.source, &self.bi_rtoks.source),
+            LangKey::TARGET => (&self.bi_text.target, &self.bi_rtoks.target),
+        };
+        if unit == self.bi_rtoks.offset_unit {
+            return Ok(rtoks.clone());
+        }
+        convert_rtoks(text, rtoks, self.bi_rtoks.offset_unit, unit)
+    }
+
+    /// Attaches the non-surface factors of each token, as parsed along with
+    /// the tokens the textunit was aligned from.
+    pub fn with_factors(
+        mut self,
+        source_factors: Vec<Vec<String>>,
+        target_factors: Vec<Vec<String>>,
+    ) -> Textunit {
+        self.bi_rtoks.source_factors = source_factors;
+        self.bi_rtoks.target_factors = target_factors;
+        self
+    }
+
+    fn is_link_in_range(&self, link: &Link) -> bool {
+        link.source < self.bi_rtoks.source.len() && link.target < self.bi_rtoks.target.len()
+    }
+
+    pub fn out_of_range_links(&self) -> Vec<Link> {
+        self.links
+            .iter()
+            .filter(|link| !self.is_link_in_range(link))
+            .cloned()
+            .collect()
+    }
+
+    pub fn drop_out_of_range_links(&mut self) {
+        let links = std::mem::take(&mut self.links);
+        self.links = links
+            .into_iter()
+            .filter(|link| self.is_link_in_range(link))
+            .collect();
+    }
+
+    pub fn check_lines(
+        reader: &Reader,
+        source_rtoks_builder: &RToksBuilder,
+        target_rtoks_builder: &RToksBuilder,
+        mode: LineCheckMode,
+    ) -> Result<(), TextunitLoadingError> {
+        match mode {
+            LineCheckMode::Counts => Self::check_line_counts(reader),
+            LineCheckMode::FirstDisagreement => {
+                Self::check_first_disagreement(reader, source_rtoks_builder, target_rtoks_builder)
+            }
+        }
+    }
+
+    fn check_line_counts(reader: &Reader) -> Result<(), TextunitLoadingError> {
+        Self::check_path_line_counts(reader.input_paths())
+    }
+
+    pub(crate) fn check_path_line_counts(paths: Vec<String>) -> Result<(), TextunitLoadingError> {
+        let mut counts = vec![];
+        for path in paths {
+            let count = Reader::count_lines(&path)
+                .map_err(|err| TextunitLoadingError::CannotReadFile(path.clone(), err))?;
+            counts.push((path, count));
+        }
+        if counts.iter().any(|(_, count)| *count != counts[0].1) {
+            return Err(TextunitLoadingError::LineCountMismatch(counts));
+        }
+        Ok(())
+    }
+
+    fn check_first_disagreement(
+        reader: &Reader,
+        source_rtoks_builder: &RToksBuilder,
+        target_rtoks_builder: &RToksBuilder,
+    ) -> Result<(), TextunitLoadingError> {
+        let paths = reader.input_paths();
+        let mut files = vec![];
+        for path in &paths {
+            let f = open_input(path)
+                .map_err(|err| TextunitLoadingError::CannotReadFile(path.clone(), Box::new(err)))?;
+            files.push(f.lines());
+        }
+        let disagree = |line_no: usize, i: usize, reason: &str| {
+            Err(TextunitLoadingError::LinesDisagree(
+                line_no,
+                paths[i].clone(),
+                reason.to_string(),
+            ))
+        };
+        let mut line_no = 0;
+        loop {
+            line_no += 1;
+            let mut lines = vec![];
+            for (path, file) in paths.iter().zip(files.iter_mut()) {
+                let line = file.next().transpose().map_err(|err| {
+                    TextunitLoadingError::CannotReadFile(path.clone(), Box::new(err))
+                })?;
+                lines.push(line);
+            }
+            if lines.iter().all(Option::is_none) {
+                return Ok(());
+            }
+            if let Some(i) = lines.iter().position(Option::is_none) {
+                return disagree(line_no, i, "file ended before the others");
+            }
+            let lines: Vec<String> = lines.into_iter().flatten().collect();
+            let links = match Reader::parse_links(&lines[0]) {
+                Ok(links) => links,
+                Err(_) => return disagree(line_no, 0, "cannot parse links"),
+            };
+            let source_toks = reader.surface_toks(&lines[1]);
+            let target_toks = reader.surface_toks(&lines[2]);
+            if source_rtoks_builder
+                .align_text_toks(&lines[3], &source_toks)
+                .is_err()
+            {
+                return disagree(line_no, 1, "tokens do not match the original line");
+            }
+            if target_rtoks_builder
+                .align_text_toks(&lines[4], &target_toks)
+                .is_err()
+            {
+                return disagree(line_no, 2, "tokens do not match the original line");
+            }
+            if links
+                .iter()
+                .any(|link| link.source >= source_toks.len() || link.target >= target_toks.len())
+            {
+                return disagree(line_no, 0, "link index exceeds token count");
+            }
+        }
+    }
+
+    /// Source and target tokens are aligned with their own builder, e.g.
+    /// from `Reader::rtoks_builder` for per-language Moses escaping.
+    pub fn iter<'a>(
+        reader: &Reader,
+        source_rtoks_builder: &'a RToksBuilder,
+        target_rtoks_builder: &'a RToksBuilder,
+    ) -> Result<TextunitIter<'a>, TextunitLoadingError> {
+        TextunitIter::new(reader, source_rtoks_builder, target_rtoks_builder)
+    }
+
     pub fn load(
         reader: &Reader,
-        rtoks_builder: &RToksBuilder,
-    ) -> Result<Vec<Textunit>, Box<Error>> {
-        let links_list = reader
-            .read_links()
-            .map_err(|err| TextunitLoadingError::CannotLoadLinks(err))?;
-        let source_toks_list = reader
-            .read_toks(LangKey::SOURCE)
+        source_rtoks_builder: &RToksBuilder,
+        target_rtoks_builder: &RToksBuilder,
+    ) -> Result<Vec<Textunit>, Box<dyn Error>> {
+        Self::load_with(
+            reader,
+            source_rtoks_builder,
+            target_rtoks_builder,
+            LinkPolicy::Keep,
+        )
+    }
+
+    pub fn load_with(
+        reader: &Reader,
+        source_rtoks_builder: &RToksBuilder,
+        target_rtoks_builder: &RToksBuilder,
+        link_policy: LinkPolicy,
+    ) -> Result<Vec<Textunit>, Box<dyn Error>> {
+        let mut textunits = vec![];
+        let iter = Self::iter(reader, source_rtoks_builder, target_rtoks_builder)?;
+        for textunit in iter.link_policy(link_policy) {
+            textunits.push(textunit?);
+        }
+        Ok(textunits)
+    }
+
+    /// One serialized `Textunit` per line, including the aligned `RTok`s.
+    pub fn write_jsonl<W: Write>(w: &mut W, textunits: &[Textunit]) -> Result<(), Box<dyn Error>> {
+        for textunit in textunits {
+            serde_json::to_writer(&mut *w, textunit)?;
+            writeln!(w)?;
+        }
+        Ok(())
+    }
+
+    pub fn save_jsonl(path: &str, textunits: &[Textunit]) -> Result<(), Box<dyn Error>> {
+        let mut w = create_output(path)?;
+        Self::write_jsonl(&mut w, textunits)?;
+        w.finish()?;
+        Ok(())
+    }
+
+    pub fn load_jsonl(reader: &Reader, path: &str) -> Result<Vec<Textunit>, Box<dyn Error>> {
+        let mut textunits = vec![];
+        for line in reader.open_lines(path)?.take(reader.config.textunit_limit) {
+            textunits.push(serde_json::from_str(&line?)?);
+        }
+        Ok(textunits)
+    }
+
+    pub fn load_lenient(
+        reader: &Reader,
+        source_rtoks_builder: &RToksBuilder,
+        target_rtoks_builder: &RToksBuilder,
+        link_policy: LinkPolicy,
+    ) -> Result<(Vec<Textunit>, Diagnostics), Box<dyn Error>> {
+        let mut textunits = vec![];
+        let mut diagnostics = Diagnostics::default();
+        let mut iter = Self::iter(reader, source_rtoks_builder, target_rtoks_builder)?
+            .link_policy(link_policy);
+        while let Some(textunit) = iter.next() {
+            match textunit {
+                Ok(textunit) => {
+                    if let Some(invalid_links) = iter.invalid_links() {
+                        diagnostics.push(Diagnostic::from_invalid_links(invalid_links));
+                    }
+                    textunits.push(textunit);
+                }
+                Err(err) => match Diagnostic::from_loading_error(iter.line_no(), &err) {
+                    Some(diagnostic) => diagnostics.push(diagnostic),
+                    None => return Err(Box::new(err)),
+                },
+            }
+        }
+        Ok((textunits, diagnostics))
+    }
+}
+
+enum ToksLines {
+    Separate(LineIter, LineIter),
+    /// `source ||| target` lines
+    FastAlign(LineIter),
+}
+
+pub struct TextunitIter<'a> {
+    source_rtoks_builder: &'a RToksBuilder,
+    target_rtoks_builder: &'a RToksBuilder,
+    links_lines: LineIter,
+    toks_lines: ToksLines,
+    source_text_lines: LineIter,
+    target_text_lines: LineIter,
+    line_no: usize,
+    remaining: usize,
+    link_policy: LinkPolicy,
+    invalid_links: Option<InvalidLinks>,
+    factor_separator: Option<String>,
+}
+
+impl<'a> TextunitIter<'a> {
+    pub fn new(
+        reader: &Reader,
+        source_rtoks_builder: &'a RToksBuilder,
+        target_rtoks_builder: &'a RToksBuilder,
+    ) -> Result<TextunitIter<'a>, TextunitLoadingError> {
+        let source_toks_lines = reader
+            .open_lines(&reader.toks_path(LangKey::SOURCE))
             .map_err(|err| TextunitLoadingError::CannotLoadToks(LangKey::SOURCE, err))?;
-        let target_toks_list = reader
-            .read_toks(LangKey::TARGET)
+        let target_toks_lines = reader
+            .open_lines(&reader.toks_path(LangKey::TARGET))
             .map_err(|err| TextunitLoadingError::CannotLoadToks(LangKey::TARGET, err))?;
-        let source_text_list = reader
-            .read_lines(LangKey::SOURCE)
+        Self::with_toks_lines(
+            reader,
+            source_rtoks_builder,
+            target_rtoks_builder,
+            ToksLines::Separate(source_toks_lines, target_toks_lines),
+        )
+    }
+
+    /// Takes tokens from the fast_align file `path` instead of the
+    /// `tok_prefix` files.
+    pub fn from_fast_align(
+        reader: &Reader,
+        source_rtoks_builder: &'a RToksBuilder,
+        target_rtoks_builder: &'a RToksBuilder,
+        path: &str,
+    ) -> Result<TextunitIter<'a>, TextunitLoadingError> {
+        let toks_lines = reader
+            .open_lines(path)
+            .map_err(|err| TextunitLoadingError::CannotLoadToks(LangKey::SOURCE, err))?;
+        Self::with_toks_lines(
+            reader,
+            source_rtoks_builder,
+            target_rtoks_builder,
+            ToksLines::FastAlign(toks_lines),
+        )
+    }
+
+    fn with_toks_lines(
+        reader: &Reader,
+        source_rtoks_builder: &'a RToksBuilder,
+        target_rtoks_builder: &'a RToksBuilder,
+        toks_lines: ToksLines,
+    ) -> Result<TextunitIter<'a>, TextunitLoadingError> {
+        let links_lines = reader
+            .open_lines(&reader.links_path())
+            .map_err(TextunitLoadingError::CannotLoadLinks)?;
+        let source_text_lines = reader
+            .open_lines(&reader.lines_path(LangKey::SOURCE))
             .map_err(|err| TextunitLoadingError::CannotLoadLines(LangKey::SOURCE, err))?;
-        let target_text_list = reader
-            .read_lines(LangKey::TARGET)
+        let target_text_lines = reader
+            .open_lines(&reader.lines_path(LangKey::TARGET))
             .map_err(|err| TextunitLoadingError::CannotLoadLines(LangKey::TARGET, err))?;
-        let mut textunits = vec![];
-        let mut line_no = 0;
-        for ((((links, source_toks), target_toks), source_text), target_text) in links_list
-            .into_iter()
-            .zip(source_toks_list.into_iter())
-            .zip(target_toks_list.into_iter())
-            .zip(source_text_list.into_iter())
-            .zip(target_text_list.into_iter())
-        {
-            line_no += 1;
-            let source_rtoks = rtoks_builder
-                .align_text_toks(&source_text, &source_toks)
-                .map_err(|err| {
-                    TextunitLoadingError::CannotAlignToks(LangKey::SOURCE, line_no, Box::new(err))
-                })?;
-            let target_rtoks = rtoks_builder
-                .align_text_toks(&target_text, &target_toks)
-                .map_err(|err| {
-                    TextunitLoadingError::CannotAlignToks(LangKey::TARGET, line_no, Box::new(err))
+        Ok(TextunitIter {
+            source_rtoks_builder,
+            target_rtoks_builder,
+            links_lines,
+            toks_lines,
+            source_text_lines,
+            target_text_lines,
+            line_no: reader.config.textunit_offset,
+            remaining: reader.config.textunit_limit,
+            link_policy: LinkPolicy::Keep,
+            invalid_links: None,
+            factor_separator: reader.config.factor_separator.clone(),
+        })
+    }
+
+    pub fn link_policy(mut self, link_policy: LinkPolicy) -> TextunitIter<'a> {
+        self.link_policy = link_policy;
+        self
+    }
+
+    pub fn line_no(&self) -> usize {
+        self.line_no
+    }
+
+    /// The out-of-range links of the textunit last returned, kept or
+    /// dropped according to the link policy.
+    pub fn invalid_links(&self) -> Option<&InvalidLinks> {
+        self.invalid_links.as_ref()
+    }
+
+    fn next_line<F>(lines: &mut LineIter, to_err: F) -> Option<Result<String, TextunitLoadingError>>
+    where
+        F: Fn(Box<dyn Error>) -> TextunitLoadingError,
+    {
+        lines
+            .next()
+            .map(|line| line.map_err(|err| to_err(Box::new(err))))
+    }
+
+    fn next_toks_lines(
+        &mut self,
+    ) -> Option<(
+        Result<String, TextunitLoadingError>,
+        Result<String, TextunitLoadingError>,
+    )> {
+        let to_source_err = |err| TextunitLoadingError::CannotLoadToks(LangKey::SOURCE, err);
+        match &mut self.toks_lines {
+            ToksLines::Separate(source_toks_lines, target_toks_lines) => {
+                let source_toks_line = Self::next_line(source_toks_lines, to_source_err)?;
+                let target_toks_line = Self::next_line(target_toks_lines, |err| {
+                    TextunitLoadingError::CannotLoadToks(LangKey::TARGET, err)
                 })?;
-            let bi_text = BiText {
-                source: source_text,
-                target: target_text,
-            };
-            let bi_rtoks = BiRToks {
-                source: source_rtoks,
-                target: target_rtoks,
-            };
-            let textunit = Textunit {
-                bi_text: bi_text,
-                bi_rtoks: bi_rtoks,
-                links: links,
-            };
-            textunits.push(textunit);
+                Some((source_toks_line, target_toks_line))
+            }
+            ToksLines::FastAlign(toks_lines) => {
+                let sides = Self::next_line(toks_lines, to_source_err)?.and_then(|line| {
+                    split_fast_align(&line)
+                        .map(|(source, target)| (source.to_string(), target.to_string()))
+                        .map_err(|err| to_source_err(Box::new(err)))
+                });
+                Some(match sides {
+                    Ok((source, target)) => (Ok(source), Ok(target)),
+                    Err(err) => (Err(err), Ok(String::new())),
+                })
+            }
         }
-        Ok(textunits)
+    }
+
+    fn next_textunit(&mut self) -> Option<Result<Textunit, TextunitLoadingError>> {
+        let links_line =
+            Self::next_line(&mut self.links_lines, TextunitLoadingError::CannotLoadLinks)?;
+        let (source_toks_line, target_toks_line) = self.next_toks_lines()?;
+        let source_text = Self::next_line(&mut self.source_text_lines, |err| {
+            TextunitLoadingError::CannotLoadLines(LangKey::SOURCE, err)
+        })?;
+        let target_text = Self::next_line(&mut self.target_text_lines, |err| {
+            TextunitLoadingError::CannotLoadLines(LangKey::TARGET, err)
+        })?;
+        self.line_no += 1;
+        Some(self.build_textunit(
+            links_line,
+            source_toks_line,
+            target_toks_line,
+            source_text,
+            target_text,
+        ))
+    }
+
+    fn build_textunit(
+        &mut self,
+        links_line: Result<String, TextunitLoadingError>,
+        source_toks_line: Result<String, TextunitLoadingError>,
+        target_toks_line: Result<String, TextunitLoadingError>,
+        source_text: Result<String, TextunitLoadingError>,
+        target_text: Result<String, TextunitLoadingError>,
+    ) -> Result<Textunit, TextunitLoadingError> {
+        let links = Reader::parse_links(&links_line?)
+            .map_err(|err| TextunitLoadingError::CannotLoadLinks(Box::new(err)))?;
+        let separator = self.factor_separator.as_deref();
+        let (source_toks, source_factors) =
+            Reader::parse_toks_with_factors(&source_toks_line?, separator);
+        let (target_toks, target_factors) =
+            Reader::parse_toks_with_factors(&target_toks_line?, separator);
+        let bi_text = BiText {
+            source: source_text?,
+            target: target_text?,
+        };
+        let mut textunit = Textunit::align_with_builders(
+            self.source_rtoks_builder,
+            self.target_rtoks_builder,
+            self.line_no,
+            bi_text,
+            &source_toks,
+            &target_toks,
+            links,
+        )?
+        .with_factors(source_factors, target_factors);
+        let invalid_links = textunit.out_of_range_links();
+        if !invalid_links.is_empty() {
+            match self.link_policy {
+                LinkPolicy::Keep => {}
+                LinkPolicy::Drop => textunit.drop_out_of_range_links(),
+                LinkPolicy::Fail => {
+                    return Err(TextunitLoadingError::LinkOutOfRange(
+                        self.line_no,
+                        invalid_links,
+                    ))
+                }
+            }
+            self.invalid_links = Some(InvalidLinks {
+                line_no: self.line_no,
+                links: invalid_links,
+            });
+        }
+        Ok(textunit)
+    }
+}
+
+impl<'a> Iterator for TextunitIter<'a> {
+    type Item = Result<Textunit, TextunitLoadingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.invalid_links = None;
+        if self.remaining == 0 {
+            return None;
+        }
+        let textunit = self.next_textunit();
+        if textunit.is_none() {
+            self.remaining = 0;
+        } else {
+            self.remaining -= 1;
+        }
+        textunit
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::redundant_field_names)]
 mod tests {
     use super::*;
     use crate::config::Config;
     use crate::config::Langs;
+    use crate::diagnostics::DiagnosticKind;
+    use crate::moses::MosesEscaping;
     use crate::rtok::RTok;
     use crate::rtoks_builder::RToksBuilder;
+    use std::collections::HashMap;
+
+    fn data_config(tok_prefix: &str, orig_prefix: &str, links: &str) -> Config {
+        let root = env!("CARGO_MANIFEST_DIR");
+        Config {
+            corpus_dir: format!("{}/data", root),
+            tok_prefix: String::from(tok_prefix),
+            langs: Langs {
+                source: String::from("en"),
+                target: String::from("th"),
+            },
+            alignment_file_path: format!("{}/data/{}", root, links),
+            orig_prefix: String::from(orig_prefix),
+            ..Config::default()
+        }
+    }
 
     #[test]
     fn load_simple_textunits() {
@@ -106,10 +636,11 @@ mod tests {
             output_amphigram_path: String::from(""),
             textunit_limit: 100,
             textunit_offset: 0,
+            ..Config::default()
         };
         let reader = Reader { config: conf };
         let rtoks_builder = RToksBuilder::new();
-        let textunits = Textunit::load(&reader, &rtoks_builder).unwrap();
+        let textunits = Textunit::load(&reader, &rtoks_builder, &rtoks_builder).unwrap();
         let rtok = RTok {
             s: 3,
             e: 5,
@@ -117,4 +648,227 @@ mod tests {
         };
         assert_eq!(textunits[0].bi_rtoks.target[1], rtok);
     }
+
+    #[test]
+    fn iter_textunits_with_offset_and_limit() {
+        let conf = Config {
+            textunit_limit: 1,
+            textunit_offset: 1,
+            ..data_config("multi-toks", "multi-lines", "multi-links")
+        };
+        let reader = Reader { config: conf };
+        let rtoks_builder = RToksBuilder::new();
+        let textunits: Vec<_> = Textunit::iter(&reader, &rtoks_builder, &rtoks_builder)
+            .unwrap()
+            .map(|textunit| textunit.unwrap())
+            .collect();
+        assert_eq!(textunits.len(), 1);
+        assert_eq!(textunits[0].bi_text.source, "The dog");
+        assert_eq!(
+            textunits[0].links,
+            vec![Link {
+                source: 1,
+                target: 0
+            }]
+        );
+    }
+
+    #[test]
+    fn load_factored_textunits() {
+        let mut conf = data_config("multi-toks", "multi-lines", "multi-links");
+        let rtoks_builder = RToksBuilder::new();
+        let plain = Textunit::load(
+            &Reader {
+                config: conf.clone(),
+            },
+            &rtoks_builder,
+            &rtoks_builder,
+        )
+        .unwrap();
+        conf.tok_prefix = String::from("factored-toks");
+        conf.factor_separator = Some(String::from("|"));
+        let reader = Reader { config: conf };
+        Textunit::check_lines(
+            &reader,
+            &rtoks_builder,
+            &rtoks_builder,
+            LineCheckMode::FirstDisagreement,
+        )
+        .unwrap();
+        let factored = Textunit::load(&reader, &rtoks_builder, &rtoks_builder).unwrap();
+        assert_eq!(factored[0].bi_rtoks.source, plain[0].bi_rtoks.source);
+        assert_eq!(factored[0].bi_rtoks.source_factors[2], vec!["NN", "cat"]);
+        assert_eq!(factored[2].bi_rtoks.target_factors[0], vec!["INTJ", "สวัสดี"]);
+        let unfactored: Vec<_> = factored
+            .into_iter()
+            .map(|textunit| textunit.with_factors(vec![], vec![]))
+            .collect();
+        assert_eq!(unfactored, plain);
+    }
+
+    #[test]
+    fn iter_with_moses_escaping_per_lang() {
+        let mut moses_escaping = HashMap::new();
+        moses_escaping.insert(
+            String::from("th"),
+            MosesEscaping {
+                entities: true,
+                aggressive_hyphens: false,
+            },
+        );
+        let conf = Config {
+            moses_escaping,
+            ..data_config("moses-toks", "moses-lines", "moses-links")
+        };
+        let mut reader = Reader { config: conf };
+        let source_rtoks_builder = reader.rtoks_builder(LangKey::SOURCE);
+        let target_rtoks_builder = reader.rtoks_builder(LangKey::TARGET);
+        let textunits =
+            Textunit::load(&reader, &source_rtoks_builder, &target_rtoks_builder).unwrap();
+        assert_eq!(textunits[0].bi_rtoks.source[3].text, "-");
+        assert_eq!(textunits[0].bi_rtoks.target[1].text, "|");
+        assert!(!reader.moses_escaping(LangKey::TARGET).aggressive_hyphens);
+        // "well @-@ known" no longer matches "well-known"
+        reader.config.moses_escaping.insert(
+            String::from("en"),
+            MosesEscaping {
+                entities: true,
+                aggressive_hyphens: false,
+            },
+        );
+        let source_rtoks_builder = reader.rtoks_builder(LangKey::SOURCE);
+        assert!(Textunit::load(&reader, &source_rtoks_builder, &target_rtoks_builder).is_err());
+    }
+
+    #[test]
+    fn check_line_counts_mismatch() {
+        let conf = data_config("multi-toks", "multi-lines", "shifted-links");
+        let reader = Reader { config: conf };
+        let rtoks_builder = RToksBuilder::new();
+        match Textunit::check_lines(
+            &reader,
+            &rtoks_builder,
+            &rtoks_builder,
+            LineCheckMode::Counts,
+        ) {
+            Err(TextunitLoadingError::LineCountMismatch(counts)) => {
+                let counts: Vec<_> = counts.into_iter().map(|(_, count)| count).collect();
+                assert_eq!(counts, vec![2, 3, 3, 3, 3]);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn check_lines_first_disagreement() {
+        let conf = data_config("multi-toks", "multi-lines", "shifted-links");
+        let reader = Reader { config: conf };
+        let rtoks_builder = RToksBuilder::new();
+        match Textunit::check_lines(
+            &reader,
+            &rtoks_builder,
+            &rtoks_builder,
+            LineCheckMode::FirstDisagreement,
+        ) {
+            Err(TextunitLoadingError::LinesDisagree(line_no, path, _)) => {
+                assert_eq!(line_no, 2);
+                assert!(path.ends_with("shifted-links"));
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn drop_out_of_range_links() {
+        let conf = data_config("multi-toks", "multi-lines", "shifted-links");
+        let reader = Reader { config: conf };
+        let rtoks_builder = RToksBuilder::new();
+        let mut iter = Textunit::iter(&reader, &rtoks_builder, &rtoks_builder)
+            .unwrap()
+            .link_policy(LinkPolicy::Drop);
+        assert!(iter.next().unwrap().is_ok());
+        assert_eq!(iter.invalid_links(), None);
+        let textunit = iter.next().unwrap().unwrap();
+        assert_eq!(
+            textunit.links,
+            vec![Link {
+                source: 0,
+                target: 0
+            }]
+        );
+        assert_eq!(
+            iter.invalid_links(),
+            Some(&InvalidLinks {
+                line_no: 2,
+                links: vec![
+                    Link {
+                        source: 1,
+                        target: 1
+                    },
+                    Link {
+                        source: 2,
+                        target: 2
+                    }
+                ]
+            })
+        );
+        assert!(iter.next().is_none());
+        assert_eq!(iter.invalid_links(), None);
+    }
+
+    #[test]
+    fn fail_on_out_of_range_links() {
+        let conf = data_config("multi-toks", "multi-lines", "shifted-links");
+        let reader = Reader { config: conf };
+        let rtoks_builder = RToksBuilder::new();
+        let results: Vec<_> = Textunit::iter(&reader, &rtoks_builder, &rtoks_builder)
+            .unwrap()
+            .link_policy(LinkPolicy::Fail)
+            .collect();
+        assert!(results[0].is_ok());
+        match &results[1] {
+            Err(TextunitLoadingError::LinkOutOfRange(line_no, _)) => assert_eq!(*line_no, 2),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(
+            Textunit::load_with(&reader, &rtoks_builder, &rtoks_builder, LinkPolicy::Fail).is_err()
+        );
+        let textunits =
+            Textunit::load_with(&reader, &rtoks_builder, &rtoks_builder, LinkPolicy::Drop).unwrap();
+        assert_eq!(textunits[1].links.len(), 1);
+    }
+
+    #[test]
+    fn load_lenient_skips_bad_textunits() {
+        let conf = data_config("bad-toks", "multi-lines", "multi-links");
+        let reader = Reader { config: conf };
+        let rtoks_builder = RToksBuilder::new();
+        let (textunits, diagnostics) =
+            Textunit::load_lenient(&reader, &rtoks_builder, &rtoks_builder, LinkPolicy::Keep)
+                .unwrap();
+        assert_eq!(textunits.len(), 2);
+        assert_eq!(diagnostics.entries.len(), 1);
+        let diagnostic = &diagnostics.entries[0];
+        assert_eq!(diagnostic.line_no, 2);
+        assert_eq!(diagnostic.kind, DiagnosticKind::CannotMatchSomeToks);
+        assert_eq!(diagnostic.context, "The| dog <- \"cat\"");
+        assert!(diagnostics
+            .to_json()
+            .unwrap()
+            .contains("\"lang\":\"SOURCE\""));
+    }
+
+    #[test]
+    fn jsonl_round_trip() {
+        let conf = data_config("multi-toks", "multi-lines", "multi-links");
+        let reader = Reader { config: conf };
+        let rtoks_builder = RToksBuilder::new();
+        let textunits = Textunit::load(&reader, &rtoks_builder, &rtoks_builder).unwrap();
+        let path = std::env::temp_dir().join("parallel_corpus_tool_textunits.jsonl.gz");
+        let path = path.to_str().unwrap();
+        Textunit::save_jsonl(path, &textunits).unwrap();
+        let loaded = Textunit::load_jsonl(&reader, path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded, textunits);
+    }
 }
@@ -0,0 +1,336 @@
+use crate::bi_text::BiText;
+use crate::config::Langs;
+use crate::lang::LangKey;
+use crate::link::Link;
+use crate::offset_unit::OffsetUnit;
+use crate::reader::Reader;
+use crate::rtok::RTok;
+use crate::textunit::Textunit;
+use crate::writer::format_links;
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use std::error::Error;
+use std::io::prelude::*;
+
+pub const ALIGNMENT_PROP: &str = "x-alignment";
+pub const SOURCE_TOKS_PROP: &str = "x-source-toks";
+pub const TARGET_TOKS_PROP: &str = "x-target-toks";
+
+// Elements inside <seg> whose content is native markup, not text.
+const NATIVE_CODE_ELEMENTS: [&[u8]; 5] = [b"bpt", b"ept", b"ph", b"it", b"ut"];
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TmxUnit {
+    pub attributes: Vec<(String, String)>,
+    pub props: Vec<(String, String)>,
+    pub bi_text: BiText,
+}
+
+impl TmxUnit {
+    pub fn prop(&self, prop_type: &str) -> Option<&str> {
+        self.props
+            .iter()
+            .find(|(t, _)| t == prop_type)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn links(&self) -> Option<Result<Vec<Link>, Box<dyn Error>>> {
+        self.prop(ALIGNMENT_PROP)
+            .map(|value| Reader::parse_links(value).map_err(From::from))
+    }
+}
+
+pub(crate) fn attributes(e: &BytesStart) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let mut attributes = vec![];
+    for attr in e.attributes() {
+        let attr = attr?;
+        attributes.push((
+            String::from_utf8_lossy(attr.key.as_ref()).to_string(),
+            attr.unescape_value()?.to_string(),
+        ));
+    }
+    Ok(attributes)
+}
+
+fn is_lang(lang: &str, wanted: &str) -> bool {
+    let lang = lang.to_lowercase();
+    let wanted = wanted.to_lowercase();
+    lang == wanted || lang.starts_with(&format!("{}-", wanted))
+}
+
+#[derive(Default)]
+struct TuState {
+    unit: TmxUnit,
+    has_source: bool,
+    has_target: bool,
+    tuv_lang: Option<String>,
+    seg: Option<String>,
+    native_depth: usize,
+    prop_type: Option<String>,
+    prop_value: String,
+}
+
+impl TuState {
+    fn push_text(&mut self, text: &str) {
+        if self.native_depth > 0 {
+            return;
+        }
+        if let Some(seg) = self.seg.as_mut() {
+            seg.push_str(text);
+        } else if self.prop_type.is_some() {
+            self.prop_value.push_str(text);
+        }
+    }
+
+    fn end_seg(&mut self, langs: &Langs) {
+        let seg = match self.seg.take() {
+            Some(seg) => seg,
+            None => return,
+        };
+        let lang = match &self.tuv_lang {
+            Some(lang) => lang,
+            None => return,
+        };
+        if !self.has_source && is_lang(lang, &langs.source) {
+            self.unit.bi_text.source = seg;
+            self.has_source = true;
+        } else if !self.has_target && is_lang(lang, &langs.target) {
+            self.unit.bi_text.target = seg;
+            self.has_target = true;
+        }
+    }
+}
+
+/// Reads `<tu>` elements that have a `<tuv>` for both languages in
+/// `langs`. Languages match case-insensitively, and `en` also matches
+/// `en-US`. Native code inside `<seg>` (`<bpt>`, `<ph>`, ...) is dropped,
+/// and `<seg/>` is read as empty text.
+pub fn read_tmx<R: BufRead>(r: R, langs: &Langs) -> Result<Vec<TmxUnit>, Box<dyn Error>> {
+    let mut reader = quick_xml::Reader::from_reader(r);
+    let mut buf = vec![];
+    let mut units = vec![];
+    let mut tu: Option<TuState> = None;
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => {
+                let name = e.name().as_ref().to_vec();
+                if name == b"tu" {
+                    let mut state = TuState::default();
+                    state.unit.attributes = attributes(&e)?;
+                    tu = Some(state);
+                } else if let Some(state) = tu.as_mut() {
+                    match &name[..] {
+                        b"tuv" => {
+                            state.tuv_lang = attributes(&e)?
+                                .into_iter()
+                                .find(|(key, _)| key == "xml:lang" || key == "lang")
+                                .map(|(_, value)| value);
+                        }
+                        b"seg" => state.seg = Some(String::new()),
+                        b"prop" if state.tuv_lang.is_none() => {
+                            state.prop_type = Some(
+                                attributes(&e)?
+                                    .into_iter()
+                                    .find(|(key, _)| key == "type")
+                                    .map_or_else(String::new, |(_, value)| value),
+                            );
+                            state.prop_value.clear();
+                        }
+                        name if state.seg.is_some() && NATIVE_CODE_ELEMENTS.contains(&name) => {
+                            state.native_depth += 1;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Event::End(e) => {
+                let name = e.name().as_ref().to_vec();
+                if name == b"tu" {
+                    if let Some(state) = tu.take() {
+                        if state.has_source && state.has_target {
+                            units.push(state.unit);
+                        }
+                    }
+                } else if let Some(state) = tu.as_mut() {
+                    match &name[..] {
+                        b"tuv" => state.tuv_lang = None,
+                        b"seg" => state.end_seg(langs),
+                        b"prop" => {
+                            if let Some(prop_type) = state.prop_type.take() {
+                                let value = std::mem::take(&mut state.prop_value);
+                                state.unit.props.push((prop_type, value));
+                            }
+                        }
+                        name if NATIVE_CODE_ELEMENTS.contains(&name) => {
+                            state.native_depth = state.native_depth.saturating_sub(1);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Event::Empty(e) => {
+                if let Some(state) = tu.as_mut() {
+                    if e.name().as_ref() == b"seg" {
+                        state.seg = Some(String::new());
+                        state.end_seg(langs);
+                    }
+                }
+            }
+            Event::Text(e) => {
+                if let Some(state) = tu.as_mut() {
+                    state.push_text(&e.unescape()?);
+                }
+            }
+            Event::CData(e) => {
+                if let Some(state) = tu.as_mut() {
+                    state.push_text(&String::from_utf8_lossy(&e.into_inner()));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok(units)
+}
+
+fn format_spans(rtoks: &[RTok]) -> String {
+    rtoks
+        .iter()
+        .map(|rtok| format!("{}-{}", rtok.s, rtok.e))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn write_prop<W: Write>(w: &mut W, prop_type: &str, value: &str) -> Result<(), Box<dyn Error>> {
+    writeln!(
+        w,
+        "      <prop type=\"{}\">{}</prop>",
+        escape(prop_type),
+        escape(value)
+    )?;
+    Ok(())
+}
+
+fn write_tuv<W: Write>(w: &mut W, lang: &str, text: &str) -> Result<(), Box<dyn Error>> {
+    writeln!(
+        w,
+        "      <tuv xml:lang=\"{}\"><seg>{}</seg></tuv>",
+        escape(lang),
+        escape(text)
+    )?;
+    Ok(())
+}
+
+/// Writes a TMX 1.4 document. With `with_alignment`, each `<tu>` carries
+/// the links in `x-alignment` and the UTF-16 token spans (`s-e`) of each
+/// side in `x-source-toks` and `x-target-toks`.
+pub fn write_tmx<W: Write>(
+    w: &mut W,
+    textunits: &[Textunit],
+    langs: &Langs,
+    with_alignment: bool,
+) -> Result<(), Box<dyn Error>> {
+    writeln!(w, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(w, "<tmx version=\"1.4\">")?;
+    writeln!(
+        w,
+        "  <header creationtool=\"{}\" creationtoolversion=\"{}\" segtype=\"sentence\" o-tmf=\"plaintext\" adminlang=\"en\" srclang=\"{}\" datatype=\"plaintext\"/>",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        escape(&langs.source)
+    )?;
+    writeln!(w, "  <body>")?;
+    for textunit in textunits {
+        writeln!(w, "    <tu>")?;
+        if with_alignment {
+            write_prop(w, ALIGNMENT_PROP, &format_links(&textunit.links))?;
+            let source_rtoks = textunit.rtoks_in(LangKey::SOURCE, OffsetUnit::Utf16)?;
+            let target_rtoks = textunit.rtoks_in(LangKey::TARGET, OffsetUnit::Utf16)?;
+            write_prop(w, SOURCE_TOKS_PROP, &format_spans(&source_rtoks))?;
+            write_prop(w, TARGET_TOKS_PROP, &format_spans(&target_rtoks))?;
+        }
+        write_tuv(w, &langs.source, &textunit.bi_text.source)?;
+        write_tuv(w, &langs.target, &textunit.bi_text.target)?;
+        writeln!(w, "    </tu>")?;
+    }
+    writeln!(w, "  </body>")?;
+    writeln!(w, "</tmx>")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bi_rtoks::BiRToks;
+    use crate::rtoks_builder::RToksBuilder;
+
+    fn langs() -> Langs {
+        Langs {
+            source: String::from("en"),
+            target: String::from("th"),
+        }
+    }
+
+    #[test]
+    fn read_tmx_units() {
+        let tmx = r#"<?xml version="1.0"?>
+<tmx version="1.4"><header srclang="en-US"/><body>
+<tu tuid="1" creationid="vee">
+  <prop type="x-domain">law</prop>
+  <tuv xml:lang="en-US"><seg>A <bpt i="1">&lt;b&gt;</bpt>black<ept i="1">&lt;/b&gt;</ept> cat &amp; dog</seg></tuv>
+  <tuv xml:lang="th"><seg>แมวดำ</seg></tuv>
+</tu>
+<tu tuid="2"><tuv xml:lang="ja"><seg>猫</seg></tuv><tuv xml:lang="en"><seg>cat</seg></tuv></tu>
+<tu tuid="3"><tuv xml:lang="en"><seg/></tuv><tuv xml:lang="th"><seg>ว่าง</seg></tuv></tu>
+</body></tmx>"#;
+        let units = read_tmx(tmx.as_bytes(), &langs()).unwrap();
+        assert_eq!(units.len(), 2);
+        assert_eq!(units[1].bi_text.source, "");
+        assert_eq!(units[1].bi_text.target, "ว่าง");
+        assert_eq!(units[0].bi_text.source, "A black cat & dog");
+        assert_eq!(units[0].bi_text.target, "แมวดำ");
+        assert_eq!(
+            units[0].attributes,
+            vec![
+                (String::from("tuid"), String::from("1")),
+                (String::from("creationid"), String::from("vee"))
+            ]
+        );
+        assert_eq!(units[0].prop("x-domain"), Some("law"));
+    }
+
+    #[test]
+    fn write_and_read_tmx_with_alignment() {
+        let rtoks_builder = RToksBuilder::new();
+        let bi_text = BiText {
+            source: String::from("black <cat>"),
+            target: String::from("แมวดำ"),
+        };
+        let bi_rtoks = BiRToks {
+            source: rtoks_builder
+                .align_text_toks(&bi_text.source, &Reader::parse_toks("black <cat>"))
+                .unwrap(),
+            target: rtoks_builder
+                .align_text_toks(&bi_text.target, &Reader::parse_toks("แมว ดำ"))
+                .unwrap(),
+            source_factors: vec![],
+            target_factors: vec![],
+            offset_unit: OffsetUnit::Utf16,
+        };
+        let textunit = Textunit {
+            bi_text,
+            bi_rtoks,
+            links: Reader::parse_links("0-1 1-0").unwrap(),
+        };
+        let mut out = vec![];
+        write_tmx(&mut out, &[textunit], &langs(), true).unwrap();
+        let units = read_tmx(&out[..], &langs()).unwrap();
+        assert_eq!(units[0].bi_text.source, "black <cat>");
+        assert_eq!(
+            units[0].links().unwrap().unwrap(),
+            Reader::parse_links("0-1 1-0").unwrap()
+        );
+        assert_eq!(units[0].prop(TARGET_TOKS_PROP), Some("0-3 3-5"));
+    }
+}
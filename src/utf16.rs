@@ -2,20 +2,34 @@ quick_error! {
     #[derive(Debug)]
     pub enum SubstringError {
         EncodeUtf16 { }
+        OutOfRange(s: usize, e: usize, len: usize) {
+            display("Range {}..{} is out of range for length {}", s, e, len)
+        }
     }
 }
 
 #[allow(dead_code)]
 pub fn substring(txt: &str, s: usize, e: usize) -> Result<String, SubstringError> {
     let a: Vec<_> = txt.encode_utf16().collect();
-    let mut sub_a = vec![];
-    for i in s..e {
-        sub_a.push(a[i]);
+    if s > e || e > a.len() {
+        return Err(SubstringError::OutOfRange(s, e, a.len()));
     }
-    String::from_utf16(&sub_a[..]).map_err(|_| SubstringError::EncodeUtf16)
+    String::from_utf16(&a[s..e]).map_err(|_| SubstringError::EncodeUtf16)
 }
 
 #[allow(dead_code)]
 pub fn utf16_len(txt: &str) -> usize {
     txt.encode_utf16().count()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substring_out_of_range() {
+        assert_eq!(substring("แมว ดำ", 4, 6).unwrap(), "ดำ");
+        assert!(substring("แมว ดำ", 4, 7).is_err());
+        assert!(substring("แมว ดำ", 3, 2).is_err());
+    }
+}
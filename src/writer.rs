@@ -0,0 +1,30 @@
+use crate::link::Link;
+use std::io;
+use std::io::prelude::*;
+
+pub fn format_links(links: &[Link]) -> String {
+    links
+        .iter()
+        .map(|link| format!("{}-{}", link.source, link.target))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn write_links_list<W: Write>(w: &mut W, links_list: &[Vec<Link>]) -> io::Result<()> {
+    for links in links_list {
+        writeln!(w, "{}", format_links(links))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::Reader;
+
+    #[test]
+    fn format_links_round_trip() {
+        let links = Reader::parse_links("1-2 3-4").unwrap();
+        assert_eq!(format_links(&links), "1-2 3-4");
+    }
+}
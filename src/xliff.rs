@@ -0,0 +1,486 @@
+use crate::bi_text::BiText;
+use crate::config::Langs;
+use crate::lang::LangKey;
+use crate::tmx::attributes;
+use crate::utf16::{substring, utf16_len};
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use std::error::Error;
+use std::io::prelude::*;
+
+// Elements whose content is native code rather than text (XLIFF 1.2).
+const NATIVE_CODE_ELEMENTS: [&[u8]; 4] = [b"ph", b"bpt", b"ept", b"it"];
+const PAIRED_ELEMENTS: [&[u8]; 3] = [b"g", b"pc", b"mrk"];
+// Content of these elements never belongs to the unit text.
+const SKIPPED_ELEMENTS: [&[u8]; 4] = [b"alt-trans", b"seg-source", b"note", b"notes"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum XliffVersion {
+    V1_2,
+    V2_0,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InlineTagKind {
+    Open,
+    Close,
+    Standalone,
+}
+
+/// An inline element removed from the text. `offset` is in UTF-16 code
+/// units of the flattened text, like `RTok.s`/`RTok.e`. `content` is the
+/// native code of `<ph>`, `<bpt>`, `<ept>` and `<it>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InlineTag {
+    pub name: String,
+    pub kind: InlineTagKind,
+    pub attributes: Vec<(String, String)>,
+    pub content: String,
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct XliffUnit {
+    pub id: String,
+    pub segment_id: Option<String>,
+    pub bi_text: BiText,
+    pub source_tags: Vec<InlineTag>,
+    pub target_tags: Vec<InlineTag>,
+}
+
+impl XliffUnit {
+    pub fn tags(&self, lang_key: LangKey) -> &[InlineTag] {
+        match lang_key {
+            LangKey::SOURCE => &self.source_tags,
+            LangKey::TARGET => &self.target_tags,
+        }
+    }
+}
+
+struct Side {
+    lang_key: LangKey,
+    text: String,
+    tags: Vec<InlineTag>,
+    native: Option<InlineTag>,
+}
+
+impl Side {
+    fn new_tag(&self, e: &BytesStart, kind: InlineTagKind) -> Result<InlineTag, Box<dyn Error>> {
+        Ok(InlineTag {
+            name: String::from_utf8_lossy(e.name().as_ref()).to_string(),
+            kind,
+            attributes: attributes(e)?,
+            content: String::new(),
+            offset: utf16_len(&self.text),
+        })
+    }
+
+    fn push_text(&mut self, text: &str) {
+        match self.native.as_mut() {
+            Some(tag) => tag.content.push_str(text),
+            None => self.text.push_str(text),
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    version: Option<XliffVersion>,
+    unit_id: Option<String>,
+    segment_id: Option<String>,
+    unit: Option<XliffUnit>,
+    side: Option<Side>,
+    skip_depth: usize,
+}
+
+impl State {
+    fn start(&mut self, e: &BytesStart, is_empty: bool) -> Result<(), Box<dyn Error>> {
+        let name = e.name().as_ref().to_vec();
+        if self.skip_depth > 0 {
+            if !is_empty {
+                self.skip_depth += 1;
+            }
+            return Ok(());
+        }
+        if let Some(side) = self.side.as_mut() {
+            if let Some(native) = side.native.as_mut() {
+                // e.g. <sub> inside native code
+                native
+                    .content
+                    .push_str(&format!("<{}>", String::from_utf8_lossy(&name)));
+                return Ok(());
+            }
+            let kind = if is_empty {
+                InlineTagKind::Standalone
+            } else if PAIRED_ELEMENTS.contains(&&name[..]) {
+                InlineTagKind::Open
+            } else {
+                InlineTagKind::Standalone
+            };
+            let tag = side.new_tag(e, kind)?;
+            if !is_empty && NATIVE_CODE_ELEMENTS.contains(&&name[..]) {
+                side.native = Some(tag);
+            } else {
+                side.tags.push(tag);
+            }
+            return Ok(());
+        }
+        match &name[..] {
+            b"xliff" => {
+                let version = attributes(e)?
+                    .into_iter()
+                    .find(|(key, _)| key == "version")
+                    .map(|(_, value)| value);
+                self.version = match version.as_deref() {
+                    Some(v) if v.starts_with('2') => Some(XliffVersion::V2_0),
+                    _ => Some(XliffVersion::V1_2),
+                };
+            }
+            b"trans-unit" | b"unit" => {
+                self.unit_id = Some(Self::id(e)?);
+                if &name[..] == b"trans-unit" {
+                    self.unit = Some(XliffUnit {
+                        id: Self::id(e)?,
+                        ..XliffUnit::default()
+                    });
+                }
+            }
+            b"segment" if self.unit_id.is_some() => {
+                let id = Self::id(e)?;
+                self.segment_id = if id.is_empty() { None } else { Some(id) };
+                self.unit = Some(XliffUnit {
+                    id: self.unit_id.clone().unwrap_or_default(),
+                    segment_id: self.segment_id.clone(),
+                    ..XliffUnit::default()
+                });
+            }
+            b"source" | b"target" if self.unit.is_some() && !is_empty => {
+                let lang_key = if &name[..] == b"source" {
+                    LangKey::SOURCE
+                } else {
+                    LangKey::TARGET
+                };
+                self.side = Some(Side {
+                    lang_key,
+                    text: String::new(),
+                    tags: vec![],
+                    native: None,
+                });
+            }
+            name if SKIPPED_ELEMENTS.contains(&name) && !is_empty => self.skip_depth = 1,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn end(&mut self, name: &[u8], units: &mut Vec<XliffUnit>) {
+        if self.skip_depth > 0 {
+            self.skip_depth -= 1;
+            return;
+        }
+        if let Some(side) = self.side.as_mut() {
+            if let Some(native) = side.native.as_mut() {
+                if NATIVE_CODE_ELEMENTS.contains(&name) && native.name.as_bytes() == name {
+                    let native = side.native.take().unwrap();
+                    side.tags.push(native);
+                } else {
+                    native
+                        .content
+                        .push_str(&format!("</{}>", String::from_utf8_lossy(name)));
+                }
+                return;
+            }
+            if name == b"source" || name == b"target" {
+                let side = self.side.take().unwrap();
+                if let Some(unit) = self.unit.as_mut() {
+                    match side.lang_key {
+                        LangKey::SOURCE => {
+                            unit.bi_text.source = side.text;
+                            unit.source_tags = side.tags;
+                        }
+                        LangKey::TARGET => {
+                            unit.bi_text.target = side.text;
+                            unit.target_tags = side.tags;
+                        }
+                    }
+                }
+            } else if PAIRED_ELEMENTS.contains(&name) {
+                side.tags.push(InlineTag {
+                    name: String::from_utf8_lossy(name).to_string(),
+                    kind: InlineTagKind::Close,
+                    attributes: vec![],
+                    content: String::new(),
+                    offset: utf16_len(&side.text),
+                });
+            }
+            return;
+        }
+        match name {
+            b"trans-unit" | b"segment" => {
+                if let Some(unit) = self.unit.take() {
+                    units.push(unit);
+                }
+            }
+            b"unit" => self.unit_id = None,
+            _ => {}
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        if self.skip_depth > 0 {
+            return;
+        }
+        if let Some(side) = self.side.as_mut() {
+            side.push_text(text);
+        }
+    }
+
+    fn id(e: &BytesStart) -> Result<String, Box<dyn Error>> {
+        Ok(attributes(e)?
+            .into_iter()
+            .find(|(key, _)| key == "id")
+            .map_or_else(String::new, |(_, value)| value))
+    }
+}
+
+/// Reads `<trans-unit>` (1.2) or `<segment>` (2.0) elements into units whose
+/// text has the inline markup removed.
+pub fn read_xliff<R: BufRead>(r: R) -> Result<(XliffVersion, Vec<XliffUnit>), Box<dyn Error>> {
+    let mut reader = quick_xml::Reader::from_reader(r);
+    let mut buf = vec![];
+    let mut units = vec![];
+    let mut state = State::default();
+    loop {
+        match reader.read_event_into(&mut buf)? {
+            Event::Start(e) => state.start(&e, false)?,
+            Event::Empty(e) => state.start(&e, true)?,
+            Event::End(e) => state.end(e.name().as_ref(), &mut units),
+            Event::Text(e) => state.push_text(&e.unescape()?),
+            Event::CData(e) => state.push_text(&String::from_utf8_lossy(&e.into_inner())),
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+    Ok((state.version.unwrap_or(XliffVersion::V1_2), units))
+}
+
+fn render_tag(tag: &InlineTag) -> String {
+    let attrs: String = tag
+        .attributes
+        .iter()
+        .map(|(key, value)| format!(" {}=\"{}\"", key, escape(value)))
+        .collect();
+    match tag.kind {
+        InlineTagKind::Open => format!("<{}{}>", tag.name, attrs),
+        InlineTagKind::Close => format!("</{}>", tag.name),
+        InlineTagKind::Standalone if tag.content.is_empty() => {
+            format!("<{}{}/>", tag.name, attrs)
+        }
+        InlineTagKind::Standalone => format!(
+            "<{}{}>{}</{}>",
+            tag.name,
+            attrs,
+            escape(&tag.content),
+            tag.name
+        ),
+    }
+}
+
+/// Serializes `text` with `tags` put back at their UTF-16 offsets. Tags at
+/// the same offset keep their order in `tags`.
+pub fn render_inline(text: &str, tags: &[InlineTag]) -> Result<String, Box<dyn Error>> {
+    let mut sorted: Vec<&InlineTag> = tags.iter().collect();
+    sorted.sort_by_key(|tag| tag.offset);
+    let len = utf16_len(text);
+    let mut out = String::new();
+    let mut s = 0;
+    for tag in sorted {
+        let offset = tag.offset.min(len);
+        out.push_str(&escape(&substring(text, s, offset)?));
+        out.push_str(&render_tag(tag));
+        s = offset;
+    }
+    out.push_str(&escape(&substring(text, s, len)?));
+    Ok(out)
+}
+
+/// Writes units as an XLIFF document. For 2.0, consecutive units with the
+/// same `id` become segments of one `<unit>`.
+pub fn write_xliff<W: Write>(
+    w: &mut W,
+    units: &[XliffUnit],
+    version: XliffVersion,
+    langs: &Langs,
+) -> Result<(), Box<dyn Error>> {
+    writeln!(w, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    match version {
+        XliffVersion::V1_2 => {
+            writeln!(
+                w,
+                "<xliff version=\"1.2\" xmlns=\"urn:oasis:names:tc:xliff:document:1.2\">"
+            )?;
+            writeln!(
+                w,
+                "  <file original=\"{}\" datatype=\"plaintext\" source-language=\"{}\" target-language=\"{}\">",
+                env!("CARGO_PKG_NAME"),
+                escape(&langs.source),
+                escape(&langs.target)
+            )?;
+            writeln!(w, "    <body>")?;
+            for unit in units {
+                writeln!(w, "      <trans-unit id=\"{}\">", escape(&unit.id))?;
+                write_side(
+                    w,
+                    "        ",
+                    "source",
+                    &unit.bi_text.source,
+                    &unit.source_tags,
+                )?;
+                write_side(
+                    w,
+                    "        ",
+                    "target",
+                    &unit.bi_text.target,
+                    &unit.target_tags,
+                )?;
+                writeln!(w, "      </trans-unit>")?;
+            }
+            writeln!(w, "    </body>")?;
+        }
+        XliffVersion::V2_0 => {
+            writeln!(
+                w,
+                "<xliff version=\"2.0\" xmlns=\"urn:oasis:names:tc:xliff:document:2.0\" srcLang=\"{}\" trgLang=\"{}\">",
+                escape(&langs.source),
+                escape(&langs.target)
+            )?;
+            writeln!(w, "  <file id=\"f1\">")?;
+            let mut i = 0;
+            while i < units.len() {
+                let id = &units[i].id;
+                writeln!(w, "    <unit id=\"{}\">", escape(id))?;
+                while i < units.len() && &units[i].id == id {
+                    let unit = &units[i];
+                    match &unit.segment_id {
+                        Some(segment_id) => {
+                            writeln!(w, "      <segment id=\"{}\">", escape(segment_id))?
+                        }
+                        None => writeln!(w, "      <segment>")?,
+                    }
+                    write_side(
+                        w,
+                        "        ",
+                        "source",
+                        &unit.bi_text.source,
+                        &unit.source_tags,
+                    )?;
+                    write_side(
+                        w,
+                        "        ",
+                        "target",
+                        &unit.bi_text.target,
+                        &unit.target_tags,
+                    )?;
+                    writeln!(w, "      </segment>")?;
+                    i += 1;
+                }
+                writeln!(w, "    </unit>")?;
+            }
+        }
+    }
+    writeln!(w, "  </file>")?;
+    writeln!(w, "</xliff>")?;
+    Ok(())
+}
+
+fn write_side<W: Write>(
+    w: &mut W,
+    indent: &str,
+    name: &str,
+    text: &str,
+    tags: &[InlineTag],
+) -> Result<(), Box<dyn Error>> {
+    writeln!(
+        w,
+        "{}<{}>{}</{}>",
+        indent,
+        name,
+        render_inline(text, tags)?,
+        name
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn langs() -> Langs {
+        Langs {
+            source: String::from("en"),
+            target: String::from("th"),
+        }
+    }
+
+    #[test]
+    fn read_xliff_1_2_inline_tags() {
+        let xliff = r#"<xliff version="1.2"><file><body>
+<trans-unit id="t1">
+  <source>A <g id="1">black</g> cat<x id="2"/> <ph id="3">&lt;br/&gt;</ph>!</source>
+  <target>แมว<g id="1">ดำ</g><x id="2"/></target>
+  <alt-trans><target>ignored</target></alt-trans>
+</trans-unit>
+</body></file></xliff>"#;
+        let (version, units) = read_xliff(xliff.as_bytes()).unwrap();
+        assert_eq!(version, XliffVersion::V1_2);
+        assert_eq!(units.len(), 1);
+        let unit = &units[0];
+        assert_eq!(unit.bi_text.source, "A black cat !");
+        assert_eq!(unit.bi_text.target, "แมวดำ");
+        let offsets: Vec<_> = unit
+            .source_tags
+            .iter()
+            .map(|tag| (tag.name.as_str(), tag.kind, tag.offset))
+            .collect();
+        assert_eq!(
+            offsets,
+            vec![
+                ("g", InlineTagKind::Open, 2),
+                ("g", InlineTagKind::Close, 7),
+                ("x", InlineTagKind::Standalone, 11),
+                ("ph", InlineTagKind::Standalone, 12),
+            ]
+        );
+        assert_eq!(unit.source_tags[3].content, "<br/>");
+        assert_eq!(
+            render_inline(&unit.bi_text.target, &unit.target_tags).unwrap(),
+            "แมว<g id=\"1\">ดำ</g><x id=\"2\"/>"
+        );
+    }
+
+    #[test]
+    fn write_and_read_xliff_2_0() {
+        let xliff = r#"<xliff version="2.0" srcLang="en" trgLang="th"><file id="f1">
+<unit id="u1">
+  <segment id="s1"><source>A <pc id="1">black</pc> cat</source></segment>
+  <segment id="s2"><source>Dog<ph id="2"/></source></segment>
+</unit>
+</file></xliff>"#;
+        let (version, mut units) = read_xliff(xliff.as_bytes()).unwrap();
+        assert_eq!(version, XliffVersion::V2_0);
+        assert_eq!(units.len(), 2);
+        assert_eq!(units[1].segment_id.as_deref(), Some("s2"));
+        units[0].bi_text.target = String::from("แมวดำ");
+        units[0].target_tags = units[0].source_tags.clone();
+        units[0].target_tags[0].offset = 3;
+        units[0].target_tags[1].offset = 5;
+        let mut out = vec![];
+        write_xliff(&mut out, &units, version, &langs()).unwrap();
+        let (_, reread) = read_xliff(&out[..]).unwrap();
+        assert_eq!(reread, units);
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .contains("<target>แมว<pc id=\"1\">ดำ</pc></target>"));
+    }
+}